@@ -0,0 +1,380 @@
+//! Renders previews of the world generation from the command line, without touching `main.rs`.
+//!
+//! ```text
+//! worldgen noisemap --size 512x1024 --octaves 6 -o noise.png
+//! worldgen terrain --seed "hello world" --noise open-simplex --shaded
+//! worldgen biome --preset assets/world_gen/default.ron -o biomes.png
+//! worldgen sweep octaves 1 2 4 8 --map terrain -o sweep/
+//! worldgen bench --size 512x1024 --runs 10
+//! ```
+//!
+//! Every flag not given is taken from the preset.
+
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Instant;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use minecraft::world_gen::{
+    biome_texture, render_in, save_texture, terrain_texture_in, BiomeMap, ClimateSettings, HeightMap,
+    NoiseMap, NoiseSettings, NoiseSource, Region, RenderSettings, TerrainRegistry, WorldGenError,
+    WorldSeed, DEFAULT_SEED, DEFAULT_SETTINGS_PATH, DEFAULT_TERRAIN_TYPES_PATH,
+};
+
+#[derive(Debug, Parser)]
+#[clap(name = "worldgen", version, about = "Renders previews of the world generation")]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// An achromatic image of the noise, black is 0 and white is 1
+    Noisemap {
+        #[clap(flatten)]
+        world: WorldArgs,
+
+        /// Where to save the image
+        #[clap(short, long, default_value = "noisemap.png")]
+        output: PathBuf,
+    },
+
+    /// The terrain, coloured by the class of every height
+    Terrain {
+        #[clap(flatten)]
+        world: WorldArgs,
+
+        /// Use the hillshaded renderer, instead of a single colour per class
+        #[clap(long)]
+        shaded: bool,
+
+        /// Where to save the image
+        #[clap(short, long, default_value = "terrain.png")]
+        output: PathBuf,
+    },
+
+    /// The biomes of the terrain, with the default climate
+    Biome {
+        #[clap(flatten)]
+        world: WorldArgs,
+
+        /// Where to save the image
+        #[clap(short, long, default_value = "biomes.png")]
+        output: PathBuf,
+    },
+
+    /// One image for every value of a parameter, to compare them side by side
+    Sweep {
+        /// The parameter to change
+        #[clap(value_enum)]
+        parameter: Parameter,
+
+        /// The values to try
+        #[clap(required = true)]
+        values: Vec<f64>,
+
+        /// Which kind of image to make for every value
+        #[clap(long, value_enum, default_value = "terrain")]
+        map: MapKind,
+
+        #[clap(flatten)]
+        world: WorldArgs,
+
+        /// The folder to save the images in, they are named after the parameter and value
+        #[clap(short, long, default_value = "sweep")]
+        output: PathBuf,
+    },
+
+    /// Times filling a noisemap on one thread against filling it on every thread
+    Bench {
+        #[clap(flatten)]
+        world: WorldArgs,
+
+        /// How many times the map is filled with each method, the average time is printed
+        #[clap(long, default_value = "5", value_parser = clap::value_parser!(u32).range(1..))]
+        runs: u32,
+    },
+}
+
+/// Everything needed to generate a world. Anything not given is taken from the preset.
+#[derive(Debug, Args)]
+struct WorldArgs {
+    /// The world seed, a number or any text
+    #[clap(long)]
+    seed: Option<String>,
+
+    /// The size of the map, as HEIGHTxWIDTH, or a single number for a square
+    #[clap(long, value_parser = parse_size, default_value = "1024")]
+    size: (usize, usize),
+
+    /// The noise type: perlin, open-simplex, super-simplex, value or worley
+    #[clap(short, long)]
+    noise: Option<NoiseSource>,
+
+    /// How many layers of noise are added together
+    #[clap(long)]
+    octaves: Option<usize>,
+
+    /// How much the frequency grows with every octave
+    #[clap(long)]
+    lacunarity: Option<f64>,
+
+    /// How much the amplitude shrinks with every octave
+    #[clap(long)]
+    persistance: Option<f64>,
+
+    /// The world preset the other flags override
+    #[clap(long, default_value = DEFAULT_SETTINGS_PATH)]
+    preset: PathBuf,
+
+    /// The classes of terrain used to colour the map
+    #[clap(long, default_value = DEFAULT_TERRAIN_TYPES_PATH)]
+    terrain_types: PathBuf,
+}
+
+impl WorldArgs {
+    fn seed(&self) -> WorldSeed {
+        self.seed.as_deref().map_or(DEFAULT_SEED, WorldSeed::from)
+    }
+
+    /// Loads the preset, and overrides it with the flags given.
+    fn settings(&self) -> Result<NoiseSettings, WorldGenError> {
+        let mut settings = NoiseSettings::load(&self.preset)?;
+        if let Some(source) = self.noise {
+            settings.source = source;
+        }
+        if let Some(octaves) = self.octaves {
+            settings.octaves = octaves;
+        }
+        if let Some(lacunarity) = self.lacunarity {
+            settings.lacunarity = lacunarity;
+        }
+        if let Some(persistance) = self.persistance {
+            settings.persistance = persistance;
+        }
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    fn region(&self) -> Region {
+        Region::new((0, 0), self.size.0, self.size.1)
+    }
+}
+
+/// A parameter of the [`NoiseSettings`] that can be swept.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Parameter {
+    Octaves,
+    Lacunarity,
+    Persistance,
+    Scale,
+}
+
+impl Parameter {
+    fn name(&self) -> &'static str {
+        match self {
+            Parameter::Octaves => "octaves",
+            Parameter::Lacunarity => "lacunarity",
+            Parameter::Persistance => "persistance",
+            Parameter::Scale => "scale",
+        }
+    }
+
+    /// Returns the settings with this parameter set to `value`.
+    fn apply(&self, settings: &NoiseSettings, value: f64) -> NoiseSettings {
+        let mut settings = settings.clone();
+        match self {
+            Parameter::Octaves => settings.octaves = value.round() as usize,
+            Parameter::Lacunarity => settings.lacunarity = value,
+            Parameter::Persistance => settings.persistance = value,
+            Parameter::Scale => settings.scale = value.round() as usize,
+        }
+        settings
+    }
+}
+
+/// The kinds of images a sweep can make.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum MapKind {
+    Noisemap,
+    Terrain,
+    Shaded,
+    Biome,
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    if let Err(e) = run(cli.command) {
+        eprintln!("Oh no\n{}", e);
+        process::exit(1);
+    }
+}
+
+fn run(command: Command) -> Result<(), WorldGenError> {
+    match command {
+        Command::Noisemap { world, output } => {
+            save_map(MapKind::Noisemap, &world, &world.settings()?, &output)
+        }
+        Command::Terrain { world, shaded, output } => {
+            let kind = if shaded { MapKind::Shaded } else { MapKind::Terrain };
+            save_map(kind, &world, &world.settings()?, &output)
+        }
+        Command::Biome { world, output } => save_map(MapKind::Biome, &world, &world.settings()?, &output),
+        Command::Sweep { parameter, values, map, world, output } => {
+            let settings = world.settings()?;
+            for value in values {
+                let settings = parameter.apply(&settings, value);
+                settings.validate()?;
+
+                let filename = format!("{}-{}.png", parameter.name(), value.to_string().replace('.', "_"));
+                save_map(map, &world, &settings, &output.join(filename))?;
+            }
+            Ok(())
+        }
+        Command::Bench { world, runs } => bench(&world, runs),
+    }
+}
+
+/// Generates the map and saves an image of it at `path`, creating any missing folders.
+fn save_map(kind: MapKind, world: &WorldArgs, settings: &NoiseSettings, path: &Path) -> Result<(), WorldGenError> {
+    let seed = world.seed();
+    let n_map = NoiseMap::from_settings(world.region(), settings, seed)?;
+
+    if let Some(folder) = path.parent() {
+        std::fs::create_dir_all(folder).map_err(|e| WorldGenError::Io(e.to_string()))?;
+    }
+    println!("Saving {}x{} image to {}", world.size.0, world.size.1, path.display());
+
+    if kind == MapKind::Noisemap {
+        return n_map.save_as_img(path);
+    }
+
+    // Noise values are mapped from [0.0; 1.0] to the heights of the world
+    let heights = settings.heights;
+    let h_map = HeightMap::from_noise_map(&n_map, heights, |val| heights.height_at(val));
+    let registry = TerrainRegistry::load(&world.terrain_types)?;
+    // The terrain classes can depend on the biome, so the terrain is identified in its biome
+    let b_map = BiomeMap::generate(&h_map, &registry, (0, 0), &ClimateSettings::default(), seed)?;
+
+    let img = match kind {
+        MapKind::Terrain => terrain_texture_in(&h_map, &registry, &b_map)?,
+        MapKind::Shaded => render_in(&h_map, &registry, &b_map, &RenderSettings::default())?,
+        MapKind::Biome => biome_texture(&b_map),
+        MapKind::Noisemap => unreachable!("noisemaps are saved above"),
+    };
+    save_texture(&img, path)
+}
+
+/// Times [`NoiseMap::fill`] against [`NoiseMap::par_fill`], and prints the average time of each.
+fn bench(world: &WorldArgs, runs: u32) -> Result<(), WorldGenError> {
+    let (seed, settings) = (world.seed(), world.settings()?);
+    let noise_fn = settings.source.build(seed);
+
+    let (height, width) = world.size;
+    let mut serial = NoiseMap::new(height, width);
+    let mut parallel = NoiseMap::new(height, width);
+    for map in [&mut serial, &mut parallel] {
+        map.apply_settings(&settings, seed)?;
+    }
+
+    let start = Instant::now();
+    for _ in 0..runs {
+        serial.fill(noise_fn, seed);
+    }
+    let serial_time = start.elapsed() / runs;
+
+    let start = Instant::now();
+    for _ in 0..runs {
+        parallel.par_fill(noise_fn, seed);
+    }
+    let parallel_time = start.elapsed() / runs;
+
+    println!(
+        "{}x{}:\tfill {:?}\tpar_fill {:?}\t({:.2}x)",
+        height,
+        width,
+        serial_time,
+        parallel_time,
+        serial_time.as_secs_f64() / parallel_time.as_secs_f64(),
+    );
+    Ok(())
+}
+
+/// Parses a size as HEIGHTxWIDTH, e.g. `512x1024`, or a single number for a square.
+fn parse_size(s: &str) -> Result<(usize, usize), String> {
+    let parse = |n: &str| n.trim().parse::<usize>().map_err(|e| format!("invalid size '{}': {}", s, e));
+
+    let (height, width) = match s.split_once(['x', 'X']) {
+        Some((height, width)) => (parse(height)?, parse(width)?),
+        None => (parse(s)?, parse(s)?),
+    };
+    match height > 0 && width > 0 {
+        true => Ok((height, width)),
+        false => Err(format!("invalid size '{}': the map can't be empty", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn cli_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn sizes_are_parsed() {
+        assert_eq!(parse_size("512x1024"), Ok((512, 1024)));
+        assert_eq!(parse_size("256"), Ok((256, 256)));
+        assert!(parse_size("0x10").is_err());
+        assert!(parse_size("big").is_err());
+    }
+
+    #[test]
+    fn flags_override_the_preset() {
+        let cli = Cli::try_parse_from([
+            "worldgen", "terrain", "--octaves", "7", "--noise", "open-simplex", "--seed", "hello", "--size", "64x32",
+        ]).unwrap();
+
+        let (world, shaded, output) = match cli.command {
+            Command::Terrain { world, shaded, output } => (world, shaded, output),
+            command => panic!("expected the terrain command, got {:?}", command),
+        };
+        assert!(!shaded);
+        assert_eq!(output, PathBuf::from("terrain.png"));
+        assert_eq!(world.size, (64, 32));
+        assert_eq!(world.seed(), WorldSeed::from("hello"));
+
+        let settings = world.settings().unwrap();
+        assert_eq!(settings.octaves, 7);
+        assert_eq!(settings.source, NoiseSource::OpenSimplex);
+        assert_eq!(settings.scale, NoiseSettings::load(DEFAULT_SETTINGS_PATH).unwrap().scale);
+    }
+
+    #[test]
+    fn bench_needs_at_least_one_run() {
+        assert!(Cli::try_parse_from(["worldgen", "bench", "--runs", "0"]).is_err());
+
+        let cli = Cli::try_parse_from(["worldgen", "bench", "--size", "16", "--runs", "2"]).unwrap();
+        match cli.command {
+            Command::Bench { world, runs } => {
+                assert_eq!(runs, 2);
+                assert_eq!(bench(&world, runs), Ok(()));
+            }
+            command => panic!("expected the bench command, got {:?}", command),
+        }
+    }
+
+    #[test]
+    fn sweeps_change_one_parameter() {
+        let settings = NoiseSettings::default();
+        let swept = Parameter::Lacunarity.apply(&settings, 3.5);
+        assert_eq!(swept.lacunarity, 3.5);
+        assert_eq!(NoiseSettings { lacunarity: settings.lacunarity, ..swept }, settings);
+    }
+}
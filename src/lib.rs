@@ -0,0 +1,4 @@
+//! The world generation of the game, as a library, 
+//! so it can be shared by the game and the `worldgen` tool (see `src/bin/worldgen.rs`).
+
+pub mod world_gen;
@@ -2,27 +2,17 @@ use bevy::{
     prelude::*,
 };
 use bevy_flycam::{FlyCam, NoCameraPlayerPlugin};
-use world_gen::{texture_demo};
-
-mod world_gen;
 
+/// Runs the game.
+/// 
+/// Previews of the world generation are made by the `worldgen` tool instead, see `src/bin/worldgen.rs`.
 fn main() {
-    // App::new()
-    //     .insert_resource(Msaa { samples: 4 })   // Anti-Aliasing
-    //     .add_plugins(DefaultPlugins)
-    //     .add_plugin(NoCameraPlayerPlugin)   // FlyCam plugin
-    //     .add_startup_system(setup)
-    //     .run();
-
-    texture_demo(
-        1024,
-        1024,
-        100,
-        4,
-        2.0,
-        0.5,
-        "0_1",
-    );
+    App::new()
+        .insert_resource(Msaa { samples: 4 })   // Anti-Aliasing
+        .add_plugins(DefaultPlugins)
+        .add_plugin(NoCameraPlayerPlugin)   // FlyCam plugin
+        .add_startup_system(setup)
+        .run();
 }
 
 /// Currently the only startup system.
@@ -0,0 +1,133 @@
+use std::error::Error;
+use std::fmt;
+
+use super::terrain::height_map::Height;
+
+/// Errors that can occur while generating a world.
+/// 
+/// Returned by the fallible (`try_*`) versions of methods that would otherwise panic, 
+/// so a bad config doesn't have to crash the game.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorldGenError {
+    /// A parameter, which must be a finite number, was NaN or infinite.
+    NonFinite { parameter: &'static str, value: f64 },
+
+    /// A range of values is empty or not finite.
+    InvalidRange { parameter: &'static str, min: f64, max: f64 },
+
+    /// A parameter was outside of the range of values it can take.
+    OutOfRange { parameter: &'static str, value: f64, min: f64, max: f64 },
+
+    /// A position outside the map was queried.
+    OutOfBounds { row: usize, column: usize, height: usize, width: usize },
+
+    /// A parameter, which must not be 0, was 0.
+    Zero(&'static str),
+
+    /// A map was used before it was filled.
+    EmptyMap,
+
+    /// No terrain type covers the given height.
+    UnknownHeight(Height),
+
+    /// A terrain class covers an empty range of heights, or a class of rivers or lakes covers any heights.
+    InvalidTerrainClass(String),
+
+    /// No terrain class covers the heights in [`from`; `to`[.
+    TerrainGap { from: Height, to: Height },
+
+    /// The named terrain class covers heights already covered by another class.
+    TerrainOverlap(String),
+
+    /// There is no terrain class of the given kind.
+    MissingTerrain(&'static str),
+
+    /// A spline has no points, a point that isn't finite, or points that aren't sorted by their input.
+    InvalidSpline(&'static str),
+
+    /// No noise source has the given name.
+    UnknownNoiseSource(String),
+
+    /// Reading or writing a file failed.
+    Io(String),
+
+    /// A config file could not be parsed or written.
+    Parse(String),
+
+    /// An image could not be read or written.
+    Image(String),
+
+    /// A file did not contain the expected number of bytes.
+    SizeMismatch { expected: usize, found: usize },
+
+    /// A list of values did not have one value for every cell of a grid.
+    LengthMismatch { expected: usize, found: usize },
+
+    /// Maps that should cover the same cells have different (height, width).
+    DimensionMismatch { expected: (usize, usize), found: (usize, usize) },
+}
+
+impl WorldGenError {
+    /// Returns `value` if it is finite, otherwise a [`NonFinite`](WorldGenError::NonFinite) error.
+    pub(crate) fn check_finite(parameter: &'static str, value: f64) -> Result<f64, WorldGenError> {
+        match value.is_finite() {
+            true => Ok(value),
+            false => Err(WorldGenError::NonFinite { parameter, value }),
+        }
+    }
+
+    /// Returns `value` if it is within [`min`; `max`], otherwise an [`OutOfRange`](WorldGenError::OutOfRange) error.
+    pub(crate) fn check_range(parameter: &'static str, value: f64, min: f64, max: f64) -> Result<f64, WorldGenError> {
+        match (min..=max).contains(&value) {
+            true => Ok(value),
+            false => Err(WorldGenError::OutOfRange { parameter, value, min, max }),
+        }
+    }
+}
+
+impl fmt::Display for WorldGenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorldGenError::NonFinite { parameter, value } => 
+                write!(f, "{} must be a finite number, got {}", parameter, value),
+            WorldGenError::InvalidRange { parameter, min, max } => 
+                write!(f, "{} must be a finite, non-empty range, got [{}; {}]", parameter, min, max),
+            WorldGenError::OutOfRange { parameter, value, min, max } => 
+                write!(f, "{} must be between {} and {}, got {}", parameter, min, max, value),
+            WorldGenError::OutOfBounds { row, column, height, width } => 
+                write!(f, "({}, {}) is outside of the {}x{} map", row, column, height, width),
+            WorldGenError::Zero(parameter) => 
+                write!(f, "{} must not be 0", parameter),
+            WorldGenError::EmptyMap => 
+                write!(f, "the map has not been filled"),
+            WorldGenError::UnknownHeight(height) => 
+                write!(f, "no terrain type covers the height {}", height),
+            WorldGenError::InvalidTerrainClass(name) => 
+                write!(f, "the terrain class \"{}\" has invalid heights", name),
+            WorldGenError::TerrainGap { from, to } => 
+                write!(f, "no terrain class covers the heights [{}; {}[", from, to),
+            WorldGenError::TerrainOverlap(name) => 
+                write!(f, "the terrain class \"{}\" overlaps another class", name),
+            WorldGenError::MissingTerrain(kind) => 
+                write!(f, "there is no terrain class for {}", kind),
+            WorldGenError::InvalidSpline(parameter) => 
+                write!(f, "{} must have at least one finite point, with strictly increasing inputs", parameter),
+            WorldGenError::UnknownNoiseSource(name) => 
+                write!(f, "unknown noise source \"{}\"", name),
+            WorldGenError::Io(e) => 
+                write!(f, "io error: {}", e),
+            WorldGenError::Parse(e) => 
+                write!(f, "parse error: {}", e),
+            WorldGenError::Image(e) => 
+                write!(f, "image error: {}", e),
+            WorldGenError::SizeMismatch { expected, found } => 
+                write!(f, "expected {} bytes, found {}", expected, found),
+            WorldGenError::LengthMismatch { expected, found } => 
+                write!(f, "expected {} values, found {}", expected, found),
+            WorldGenError::DimensionMismatch { expected, found } => 
+                write!(f, "expected a {}x{} map, found {}x{}", expected.0, expected.1, found.0, found.1),
+        }
+    }
+}
+
+impl Error for WorldGenError {}
@@ -0,0 +1,426 @@
+use std::ops::{Index, IndexMut};
+
+use super::error::WorldGenError;
+
+/// The 8 neighbours of a cell, as (row, column) offsets, and their distance to it.
+pub(crate) const NEIGHBOURS: [(isize, isize, f64); 8] = [
+    (-1, -1, std::f64::consts::SQRT_2), (-1, 0, 1.0), (-1, 1, std::f64::consts::SQRT_2),
+    (0, -1, 1.0),                                     (0, 1, 1.0),
+    (1, -1, std::f64::consts::SQRT_2),  (1, 0, 1.0),  (1, 1, std::f64::consts::SQRT_2),
+];
+
+/// A 2D grid of values, stored row by row in a single vec.
+///
+/// This is the backbone of every map layer ([`NoiseMap`](super::noise::noise_map::NoiseMap),
+/// [`HeightMap`](super::terrain::height_map::HeightMap), ...),
+/// so they all agree on how a (row, column) is found, and rows are never mixed up with columns.
+///
+/// Can be indexed with `grid[(row, column)]`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Grid2D<T> {
+    height: usize,
+    width: usize,
+    values: Vec<T>,
+}
+
+/// Methods for creating a Grid2D.
+impl<T> Grid2D<T> {
+    /// Creates a grid with the given size, where every cell is `value`.
+    pub fn new(height: usize, width: usize, value: T) -> Self
+    where
+        T: Clone
+    {
+        Grid2D {
+            height,
+            width,
+            values: vec![value; height * width],
+        }
+    }
+
+    /// Creates a grid with the given size, where the value at (row, column) is `f(row, column)`.
+    pub fn from_fn<F>(height: usize, width: usize, mut f: F) -> Self
+    where
+        F: FnMut(usize, usize) -> T
+    {
+        let mut values = Vec::with_capacity(height * width);
+        for row in 0..height {
+            for column in 0..width {
+                values.push(f(row, column));
+            }
+        }
+
+        Grid2D {
+            height,
+            width,
+            values,
+        }
+    }
+
+    /// Creates a grid from values given row by row.
+    ///
+    /// # Errors
+    /// Returns an error if there isn't exactly `height * width` values.
+    pub fn from_vec(height: usize, width: usize, values: Vec<T>) -> Result<Self, WorldGenError> {
+        if values.len() != height * width {
+            return Err(WorldGenError::LengthMismatch { expected: height * width, found: values.len() });
+        }
+
+        Ok(Grid2D {
+            height,
+            width,
+            values,
+        })
+    }
+}
+
+/// Methods for accessing data in the Grid2D.
+impl<T> Grid2D<T> {
+    /// Returns the index of the given row, column in the list of values.
+    ///
+    /// # Panics
+    /// Panics if the position is outside the grid.
+    fn index(&self, row: usize, column: usize) -> usize {
+        assert!(
+            row < self.height && column < self.width,
+            "({}, {}) is outside of the {}x{} grid", row, column, self.height, self.width,
+        );
+
+        row * self.width + column
+    }
+
+    /// Returns the value at the given position.
+    ///
+    /// # Panics
+    /// Panics if the position is outside the grid.
+    pub fn get(&self, row: usize, column: usize) -> &T {
+        &self.values[ self.index(row, column) ]
+    }
+
+    /// Returns the value at the given position, or an error if it is outside the grid.
+    pub fn try_get(&self, row: usize, column: usize) -> Result<&T, WorldGenError> {
+        if !self.contains(row, column) {
+            return Err(WorldGenError::OutOfBounds { row, column, height: self.height, width: self.width });
+        }
+        Ok(self.get(row, column))
+    }
+
+    /// Returns the value at the given position, so it can be changed in place.
+    pub fn get_mut(&mut self, row: usize, column: usize) -> &mut T {
+        let index = self.index(row, column);
+        &mut self.values[index]
+    }
+
+    /// Sets the value at the given position.
+    pub fn set(&mut self, row: usize, column: usize, value: T) {
+        *self.get_mut(row, column) = value;
+    }
+
+    /// Returns true if the position is inside the grid.
+    pub fn contains(&self, row: usize, column: usize) -> bool {
+        row < self.height && column < self.width
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the (height, width) of the grid.
+    pub fn size(&self) -> (usize, usize) {
+        (self.height, self.width)
+    }
+
+    /// Returns the number of cells in the grid.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the values row by row.
+    pub fn as_slice(&self) -> &[T] {
+        &self.values
+    }
+
+    /// Returns the values row by row, so they can be changed in place.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.values
+    }
+
+    /// Returns the values row by row.
+    pub fn into_vec(self) -> Vec<T> {
+        self.values
+    }
+}
+
+/// Methods for iterating over the Grid2D.
+impl<T> Grid2D<T> {
+    /// Returns an iterator over the values, row by row.
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.values.iter()
+    }
+
+    /// Returns an iterator over the values, row by row, so they can be changed in place.
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.values.iter_mut()
+    }
+
+    /// Returns an iterator over the (row, column) and value of every cell, row by row.
+    pub fn cells(&self) -> impl Iterator<Item = ((usize, usize), &T)> {
+        let width = self.width;
+        self.values
+            .iter()
+            .enumerate()
+            .map(move |(i, value)| ((i / width, i % width), value))
+    }
+
+    /// Returns the given row.
+    pub fn row(&self, row: usize) -> &[T] {
+        assert!(row < self.height, "row {} is outside of the {}x{} grid", row, self.height, self.width);
+        &self.values[row * self.width..(row + 1) * self.width]
+    }
+
+    /// Returns an iterator over the rows, from top to bottom.
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        (0..self.height).map(move |row| self.row(row))
+    }
+
+    /// Returns an iterator over the given column, from top to bottom.
+    pub fn column(&self, column: usize) -> impl Iterator<Item = &T> {
+        assert!(column < self.width, "column {} is outside of the {}x{} grid", column, self.height, self.width);
+        self.values.iter().skip(column).step_by(self.width)
+    }
+
+    /// Returns an iterator over the columns, from left to right.
+    pub fn columns(&self) -> impl Iterator<Item = impl Iterator<Item = &T>> {
+        (0..self.width).map(move |column| self.column(column))
+    }
+
+    /// Returns a view of the part of the grid with the given size, starting at (row, column).
+    ///
+    /// # Errors
+    /// Returns an error if the view doesn't fit inside the grid.
+    pub fn view(&self, row: usize, column: usize, height: usize, width: usize) -> Result<GridView<'_, T>, WorldGenError> {
+        if row + height > self.height || column + width > self.width {
+            return Err(WorldGenError::OutOfBounds {
+                row: row + height, column: column + width, height: self.height, width: self.width,
+            });
+        }
+
+        Ok(GridView {
+            grid: self,
+            origin: (row, column),
+            height,
+            width,
+        })
+    }
+}
+
+/// Methods for creating new grids from a Grid2D.
+impl<T> Grid2D<T> {
+    /// Returns a grid of the same size, where every value has been passed through `f`.
+    pub fn map<U, F>(&self, f: F) -> Grid2D<U>
+    where
+        F: FnMut(&T) -> U
+    {
+        Grid2D {
+            height: self.height,
+            width: self.width,
+            values: self.values.iter().map(f).collect(),
+        }
+    }
+
+    /// Returns a grid where every value is `f` of the values at the same position in the two grids.
+    ///
+    /// # Errors
+    /// Returns an error if the grids are not the same size.
+    pub fn zip<U, V, F>(&self, other: &Grid2D<U>, mut f: F) -> Result<Grid2D<V>, WorldGenError>
+    where
+        F: FnMut(&T, &U) -> V
+    {
+        if self.size() != other.size() {
+            return Err(WorldGenError::DimensionMismatch { expected: self.size(), found: other.size() });
+        }
+
+        Ok(Grid2D {
+            height: self.height,
+            width: self.width,
+            values: self.values.iter().zip(&other.values).map(|(a, b)| f(a, b)).collect(),
+        })
+    }
+}
+
+impl<T> Index<(usize, usize)> for Grid2D<T> {
+    type Output = T;
+
+    fn index(&self, (row, column): (usize, usize)) -> &T {
+        self.get(row, column)
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Grid2D<T> {
+    fn index_mut(&mut self, (row, column): (usize, usize)) -> &mut T {
+        self.get_mut(row, column)
+    }
+}
+
+/// A rectangular part of a [`Grid2D`], e.g. a single chunk of a larger map.
+///
+/// Positions in the view are relative to its top left corner.
+#[derive(Debug, Copy, Clone)]
+pub struct GridView<'g, T> {
+    grid: &'g Grid2D<T>,
+    origin: (usize, usize),     // The (row, column) of the top left corner in the grid
+    height: usize,
+    width: usize,
+}
+
+impl<'g, T> GridView<'g, T> {
+    /// Returns the value at the given position in the view.
+    ///
+    /// # Panics
+    /// Panics if the position is outside the view.
+    pub fn get(&self, row: usize, column: usize) -> &'g T {
+        assert!(
+            row < self.height && column < self.width,
+            "({}, {}) is outside of the {}x{} view", row, column, self.height, self.width,
+        );
+        self.grid.get(self.origin.0 + row, self.origin.1 + column)
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the (row, column) of the top left corner of the view in the grid.
+    pub fn get_origin(&self) -> (usize, usize) {
+        self.origin
+    }
+
+    /// Returns the given row of the view.
+    pub fn row(&self, row: usize) -> &'g [T] {
+        assert!(row < self.height, "row {} is outside of the {}x{} view", row, self.height, self.width);
+        &self.grid.row(self.origin.0 + row)[self.origin.1..self.origin.1 + self.width]
+    }
+
+    /// Returns an iterator over the rows of the view, from top to bottom.
+    pub fn rows(&self) -> impl Iterator<Item = &'g [T]> + '_ {
+        (0..self.height).map(move |row| self.row(row))
+    }
+
+    /// Copies the view into a grid of its own.
+    pub fn to_grid(&self) -> Grid2D<T>
+    where
+        T: Clone
+    {
+        Grid2D::from_fn(self.height, self.width, |row, column| self.get(row, column).clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 3x5 grid where every value is its own (row, column).
+    fn positions() -> Grid2D<(usize, usize)> {
+        Grid2D::from_fn(3, 5, |row, column| (row, column))
+    }
+
+    #[test]
+    fn indexing_is_row_major() {
+        let grid = positions();
+        assert_eq!(grid.size(), (3, 5));
+        assert_eq!(grid.len(), 15);
+
+        for row in 0..3 {
+            for column in 0..5 {
+                assert_eq!(grid[(row, column)], (row, column));
+                assert_eq!(grid.as_slice()[row * 5 + column], (row, column));
+            }
+        }
+        assert!(grid.cells().all(|(position, &value)| position == value));
+
+        assert_eq!(grid.try_get(2, 4), Ok(&(2, 4)));
+        assert!(matches!(grid.try_get(4, 2), Err(WorldGenError::OutOfBounds { .. })));
+        assert!(matches!(grid.try_get(0, 5), Err(WorldGenError::OutOfBounds { .. })));
+    }
+
+    #[test]
+    #[should_panic]
+    fn indexing_outside_panics() {
+        // Would be inside a 5x3 grid, so catches mixed up rows and columns
+        positions().get(4, 2);
+    }
+
+    #[test]
+    fn rows_and_columns() {
+        let grid = positions();
+
+        assert_eq!(grid.rows().count(), 3);
+        assert_eq!(grid.row(1), &[(1, 0), (1, 1), (1, 2), (1, 3), (1, 4)]);
+        assert_eq!(grid.columns().count(), 5);
+        assert_eq!(grid.column(3).copied().collect::<Vec<_>>(), vec![(0, 3), (1, 3), (2, 3)]);
+
+        for (column, values) in grid.columns().enumerate() {
+            assert!(values.enumerate().all(|(row, &value)| value == (row, column)));
+        }
+    }
+
+    #[test]
+    fn views_are_relative_to_their_corner() {
+        let grid = positions();
+        let view = grid.view(1, 2, 2, 3).unwrap();
+
+        assert_eq!(view.get(0, 0), &(1, 2));
+        assert_eq!(view.get(1, 2), &(2, 4));
+        assert_eq!(view.row(1), &[(2, 2), (2, 3), (2, 4)]);
+        assert_eq!(view.rows().count(), 2);
+        assert_eq!(view.to_grid(), Grid2D::from_fn(2, 3, |row, column| (row + 1, column + 2)));
+
+        assert!(grid.view(1, 2, 3, 3).is_err());
+        assert!(grid.view(0, 0, 5, 3).is_err());
+    }
+
+    #[test]
+    fn map_and_zip() {
+        let grid = positions();
+        let rows = grid.map(|&(row, _)| row);
+        let columns = grid.map(|&(_, column)| column);
+
+        let sums = rows.zip(&columns, |row, column| row * 10 + column).unwrap();
+        assert_eq!(sums[(2, 3)], 23);
+        assert_eq!(sums.size(), (3, 5));
+
+        let transposed = Grid2D::new(5, 3, 0);
+        assert_eq!(
+            rows.zip(&transposed, |a, b| a + b),
+            Err(WorldGenError::DimensionMismatch { expected: (3, 5), found: (5, 3) }),
+        );
+    }
+
+    #[test]
+    fn from_vec_checks_the_length() {
+        let grid = Grid2D::from_vec(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap();
+        assert_eq!(grid[(1, 0)], 4);
+        assert_eq!(grid.clone().into_vec(), vec![1, 2, 3, 4, 5, 6]);
+
+        let mut grid = grid;
+        grid.set(0, 2, 9);
+        grid[(1, 1)] += 10;
+        assert_eq!(grid.as_slice(), &[1, 2, 9, 4, 15, 6]);
+
+        assert_eq!(
+            Grid2D::from_vec(3, 3, vec![0; 6]),
+            Err(WorldGenError::LengthMismatch { expected: 9, found: 6 }),
+        );
+    }
+}
@@ -1 +1,7 @@
+pub mod domain_warp;
+pub mod falloff;
+pub mod fractal;
 pub mod noise_map;
+pub mod noise_settings;
+pub mod noise_source;
+pub mod noise_volume;
@@ -0,0 +1,62 @@
+use noise::{NoiseFn, Perlin, Seedable};
+use rand::prelude::*;
+
+use crate::world_gen::seed::WorldSeed;
+use super::noise_map::DEFAULT_SCALE;
+
+/// Displaces the points a noise map is sampled at, using a secondary noise field.
+/// 
+/// Warping the domain of a noise function turns the usual round blobs of noise 
+/// into swirly continents with more natural coastlines.
+#[derive(Debug, Clone)]
+pub struct DomainWarp {
+    scale: f64,     // Size of the features of the warp, measured in cells like the scale of a NoiseMap
+    strength: f64,  // The largest distance a point can be displaced, measured in cells
+    noise_fn: Perlin,
+    offsets: [(f64, f64); 2],   // Sample offsets of the noise field displacing x and y respectively
+}
+
+impl DomainWarp {
+    /// Creates a new DomainWarp, with its noise field seeded from `seed`.
+    /// 
+    /// # Panics
+    /// Panics if `strength` is not finite.
+    /// 
+    /// ## Note
+    /// The scale cannot be 0. If 0 is parsed into this method, 
+    /// the scale will be set to [DEFAULT_SCALE]
+    pub fn new(scale: usize, strength: f64, seed: WorldSeed) -> Self {
+        assert!(strength.is_finite(), "Non-finite domain warp strength");
+
+        let scale = match scale {
+            0 => DEFAULT_SCALE,
+            _ => scale,
+        };
+
+        // Sampling the same noise field far apart gives two independent fields for x and y
+        let mut prng = seed.rng();
+        let mut offset = || (
+            prng.gen_range(-1_000_000..1_000_000) as f64,
+            prng.gen_range(-1_000_000..1_000_000) as f64,
+        );
+
+        DomainWarp {
+            scale: scale as f64,
+            strength,
+            noise_fn: Perlin::new().set_seed(seed.noise_seed()),
+            offsets: [offset(), offset()],
+        }
+    }
+
+    /// Returns the world-space `point` displaced by the noise field of the warp.
+    pub fn displace(&self, [x, y]: [f64; 2]) -> [f64; 2] {
+        let sample = |(offset_x, offset_y): (f64, f64)| {
+            self.noise_fn.get([x / self.scale + offset_x, y / self.scale + offset_y])
+        };
+
+        [
+            x + sample(self.offsets[0]) * self.strength,
+            y + sample(self.offsets[1]) * self.strength,
+        ]
+    }
+}
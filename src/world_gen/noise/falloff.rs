@@ -0,0 +1,158 @@
+use super::noise_map::Region;
+
+/// Defines how the distance from the center of a [`FalloffMask`] is measured.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FalloffShape {
+    /// Euclidean distance, giving round islands.
+    Radial,
+
+    /// The largest distance along either axis, giving square continents.
+    Square,
+}
+
+/// Defines how strongly a [`FalloffMask`] sinks the terrain, based on the distance from its center.
+/// 
+/// Distances are given relative to the radius of the mask, so 0.0 is the center and 1.0 is the edge.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FalloffCurve {
+    /// A smooth s-curve, `x^steepness / (x^steepness + (shift - shift * x)^steepness)`.
+    /// 
+    /// `steepness` controls how sharp the transition is, 
+    /// and `shift` moves the transition towards the edge as it grows.
+    Smooth { steepness: f64, shift: f64 },
+
+    /// Linearly interpolates between the given (distance, falloff) points, which must be sorted by distance.
+    /// 
+    /// Distances before the first and after the last point use the falloff of those points.
+    Custom(Vec<(f64, f64)>),
+}
+
+impl FalloffCurve {
+    /// Returns the falloff at the given relative distance, between 0.0 (untouched) and 1.0 (fully sunk).
+    pub fn falloff(&self, distance: f64) -> f64 {
+        let distance = distance.max(0.0);
+        let falloff = match self {
+            FalloffCurve::Smooth { steepness, shift } => {
+                let near = distance.powf(*steepness);
+                let far = (shift - shift * distance).max(0.0).powf(*steepness);
+                if near + far == 0.0 { 1.0 } else { near / (near + far) }
+            }
+            FalloffCurve::Custom(points) => interpolate(points, distance),
+        };
+        falloff.clamp(0.0, 1.0)
+    }
+}
+
+impl Default for FalloffCurve {
+    fn default() -> Self {
+        FalloffCurve::Smooth { steepness: 3.0, shift: 2.2 }
+    }
+}
+
+/// Linearly interpolates between sorted (x, y) points.
+fn interpolate(points: &[(f64, f64)], x: f64) -> f64 {
+    match points {
+        [] => 0.0,
+        [(_, y)] => *y,
+        [(first_x, first_y), ..] if x <= *first_x => *first_y,
+        _ => points
+            .windows(2)
+            .find(|pair| x <= pair[1].0)
+            .map(|pair| {
+                let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+                if x1 == x0 { y1 } else { y0 + (y1 - y0) * (x - x0) / (x1 - x0) }
+            })
+            .unwrap_or(points[points.len() - 1].1),
+    }
+}
+
+/// A mask that sinks terrain the further it is from a center point, 
+/// used to turn endless noise into bounded islands or continents.
+/// 
+/// The mask is defined in world-space, so every region of the world agrees on where the island is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FalloffMask {
+    shape: FalloffShape,
+    curve: FalloffCurve,
+    center: (f64, f64),     // World-space (row, column) of the center of the mask
+    radius: f64,            // Distance from the center to the edge of the mask, measured in cells
+}
+
+impl FalloffMask {
+    /// Creates a mask centered at world-space `center`, reaching `radius` cells in every direction.
+    /// 
+    /// # Panics
+    /// Panics if `radius` is not a positive, finite number.
+    pub fn new(shape: FalloffShape, center: (f64, f64), radius: f64) -> Self {
+        assert!(radius.is_finite() && radius > 0.0, "Invalid falloff radius");
+
+        FalloffMask {
+            shape,
+            curve: FalloffCurve::default(),
+            center,
+            radius,
+        }
+    }
+
+    /// Creates a mask whose edge touches the sides of the given region.
+    pub fn covering(shape: FalloffShape, region: Region) -> Self {
+        let half_height = region.height as f64 / 2.0;
+        let half_width = region.width as f64 / 2.0;
+
+        FalloffMask::new(
+            shape,
+            (region.origin.0 as f64 + half_height, region.origin.1 as f64 + half_width),
+            half_height.min(half_width).max(f64::MIN_POSITIVE),
+        )
+    }
+
+    /// Replaces the [`FalloffCurve`] of the mask.
+    pub fn with_curve(mut self, curve: FalloffCurve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    /// Returns the falloff at the given world-space cell, between 0.0 (untouched) and 1.0 (fully sunk).
+    pub fn falloff(&self, row: i64, column: i64) -> f64 {
+        // Measure from the center of the cell, so masks covering a region are symmetric
+        let d_row = (row as f64 + 0.5 - self.center.0).abs() / self.radius;
+        let d_column = (column as f64 + 0.5 - self.center.1).abs() / self.radius;
+
+        let distance = match self.shape {
+            FalloffShape::Radial => d_row.hypot(d_column),
+            FalloffShape::Square => d_row.max(d_column),
+        };
+        self.curve.falloff(distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smooth_curve_sinks_edges() {
+        let curve = FalloffCurve::default();
+        assert!(curve.falloff(0.0) < 0.01);
+        assert!(curve.falloff(1.0) > 0.99);
+        assert_eq!(curve.falloff(2.0), 1.0);
+    }
+
+    #[test]
+    fn custom_curve_interpolates() {
+        let curve = FalloffCurve::Custom(vec![(0.5, 0.0), (1.0, 1.0)]);
+        assert_eq!(curve.falloff(0.0), 0.0);
+        assert_eq!(curve.falloff(0.75), 0.5);
+        assert_eq!(curve.falloff(3.0), 1.0);
+    }
+
+    #[test]
+    fn covering_mask_is_centered() {
+        let mask = FalloffMask::covering(FalloffShape::Square, Region::new((10, -20), 64, 32));
+        let linear = FalloffCurve::Custom(vec![(0.0, 0.0), (1.0, 1.0)]);
+        let mask = mask.with_curve(linear);
+
+        assert_eq!(mask.falloff(10, -20), mask.falloff(10 + 63, -20 + 31));
+        assert!(mask.falloff(10 + 32, -20 + 16) < 0.05);
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Defines how the octaves of a noise map are shaped before they are summed.
+/// 
+/// Every mode keeps the usual lacunarity and persistance semantics, 
+/// only the value each octave contributes changes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FractalMode {
+    /// Fractal brownian motion. Every octave is summed as is.
+    #[default]
+    Fbm,
+
+    /// Ridged multifractal. Every octave is folded and inverted into sharp ridges, 
+    /// and weighted by the octave before it, so valleys stay smooth while ridges get detailed.
+    /// 
+    /// Good for mountain ranges.
+    Ridged,
+
+    /// Rounded lumps separated by creases. Every octave is folded at 0, and eased so the tops are rounded.
+    /// 
+    /// Good for hills and river valleys.
+    Billow,
+
+    /// The absolute value of every octave is summed, leaving sharp creases wherever an octave crosses 0.
+    /// 
+    /// Good for eroded looking terrain.
+    Turbulence,
+}
+
+/// How strongly an octave of [`FractalMode::Ridged`] affects the weight of the next octave.
+const RIDGED_GAIN: f64 = 2.0;
+
+impl FractalMode {
+    /// All fractal modes, mostly useful for demos.
+    pub const ALL: [FractalMode; 4] = [
+        FractalMode::Fbm,
+        FractalMode::Ridged,
+        FractalMode::Billow,
+        FractalMode::Turbulence,
+    ];
+
+    /// Shapes the value of a single octave, before it is scaled by its amplitude.
+    /// 
+    /// `weight` starts at 1.0 and is carried from one octave to the next.
+    pub fn octave(&self, value: f64, weight: &mut f64) -> f64 {
+        match self {
+            FractalMode::Fbm => value,
+            FractalMode::Ridged => {
+                let signal = (1.0 - value.abs()).powi(2) * *weight;
+                *weight = (signal * RIDGED_GAIN).clamp(0.0, 1.0);
+                signal
+            }
+            FractalMode::Billow => 1.0 - 2.0 * (1.0 - value.abs()).powi(2),
+            FractalMode::Turbulence => value.abs(),
+        }
+    }
+
+    /// Returns the range the octaves can sum to, given the sum of the amplitudes of all octaves.
+    pub fn range(&self, amplitude_bound: f64) -> (f64, f64) {
+        match self {
+            FractalMode::Fbm | FractalMode::Billow => (-amplitude_bound, amplitude_bound),
+            FractalMode::Ridged | FractalMode::Turbulence => (0.0, amplitude_bound),
+        }
+    }
+
+    /// Returns the name of the fractal mode.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FractalMode::Fbm => "fbm",
+            FractalMode::Ridged => "ridged",
+            FractalMode::Billow => "billow",
+            FractalMode::Turbulence => "turbulence",
+        }
+    }
+}
+
+impl fmt::Display for FractalMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
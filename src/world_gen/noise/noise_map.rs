@@ -1,28 +1,177 @@
-use image::ImageResult;
+use std::fs;
+use std::path::Path;
+
+use image::{GrayImage, ImageBuffer, Luma};
 use noise::NoiseFn;
 use rand::prelude::*;
-use rand_seeder::Seeder;   // Seeder is not cryptographically safe, but that does not matter for us
-use rand_pcg::Pcg64;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::world_gen::error::WorldGenError;
+use crate::world_gen::grid::Grid2D;
+use crate::world_gen::seed::WorldSeed;
+use super::domain_warp::DomainWarp;
+use super::falloff::FalloffMask;
+use super::fractal::FractalMode;
 
 pub const DEFAULT_SCALE: usize = 100;
 pub const DEFAULT_OCTAVES: usize = 1;
 pub const DEFAULT_LACUNARITY: f64 = 1.0;
+pub const DEFAULT_PERSISTANCE: f64 = 1.0;
+
+/// A rectangular section of the world, measured in cells.
+/// 
+/// `origin` is the world-space (row, column) of the first cell in the region, 
+/// so two regions sampled from the same noise function line up wherever they meet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub origin: (i64, i64),
+    pub height: usize,
+    pub width: usize,
+}
+
+impl Region {
+    /// Creates a region of the given size, starting at `origin`.
+    pub fn new(origin: (i64, i64), height: usize, width: usize) -> Self {
+        Region { origin, height, width }
+    }
+
+    /// Creates the square region covered by the chunk at chunk coordinates (`chunk_row`, `chunk_column`).
+    /// 
+    /// Chunk (0, 0) starts at world-space (0, 0), chunk (1, 0) starts at (`chunk_size`, 0) and so on.
+    pub fn chunk(chunk_row: i64, chunk_column: i64, chunk_size: usize) -> Self {
+        let size = chunk_size as i64;
+        Region::new(
+            (chunk_row * size, chunk_column * size),
+            chunk_size,
+            chunk_size,
+        )
+    }
+}
+
+/// Defines how the raw sum of octaves is mapped into [0; 1].
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Normalization {
+    /// Uses the smallest and largest value of the map itself.
+    /// 
+    /// The value of a cell depends on everything else in the map, 
+    /// so maps of different regions or sizes will not line up.
+    #[default]
+    Buffer,
+
+    /// Uses the range the octaves could possibly sum to, 
+    /// derived from the number of octaves, the persistance and the [`FractalMode`].
+    AmplitudeBound,
+
+    /// Maps the given range into [0; 1]. Values outside the range are clamped.
+    Fixed { min: f64, max: f64 },
+}
+
+impl Normalization {
+    /// Maps the raw octave sums in `values` into [0; 1].
+    /// 
+    /// `octave_range` is the range the octaves can possibly sum to (see [`amplitude_bound`]).
+    pub(super) fn apply(&self, values: &mut [f64], octave_range: (f64, f64)) {
+        match *self {
+            Normalization::Buffer => normalize_by_buffer(values),
+            Normalization::AmplitudeBound => 
+                normalize_by_range(values, octave_range.0, octave_range.1),
+            Normalization::Fixed { min, max } => normalize_by_range(values, min, max),
+        }
+    }
+
+    /// Returns an error if a [`Fixed`](Normalization::Fixed) range is not finite, 
+    /// or its `min` is not smaller than its `max`.
+    pub fn validate(&self) -> Result<(), WorldGenError> {
+        match *self {
+            Normalization::Fixed { min, max } 
+                if !(min.is_finite() && max.is_finite() && min < max) => 
+                Err(WorldGenError::InvalidRange { parameter: "normalization", min, max }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Returns the largest absolute value the octaves can sum to.
+/// 
+/// Every octave is sampled from a noise function in [-1; 1] and scaled by its amplitude,
+/// so the sum is bounded by the sum of all amplitudes.
+pub(super) fn amplitude_bound(octaves: usize, persistance: f64) -> f64 {
+    let mut bound = 0.0;
+    let mut amplitude: f64 = 1.0;
+    for _ in 0..octaves {
+        bound += amplitude.abs();
+        amplitude *= persistance;
+    }
+    bound
+}
+
+/// Returns the lacunarity to use, [`DEFAULT_LACUNARITY`] if it is 0.
+/// 
+/// Shared by the setters of [`NoiseMap`] and [`NoiseVolume`](super::noise_volume::NoiseVolume).
+pub(super) fn checked_lacunarity(lacunarity: f64) -> Result<f64, WorldGenError> {
+    Ok(match WorldGenError::check_finite("lacunarity", lacunarity)? {
+        0.0 => DEFAULT_LACUNARITY,
+        lacunarity => lacunarity,
+    })
+}
+
+/// Returns the persistance to use, or an error if it is not finite.
+/// 
+/// Shared by the setters of [`NoiseMap`] and [`NoiseVolume`](super::noise_volume::NoiseVolume).
+pub(super) fn checked_persistance(persistance: f64) -> Result<f64, WorldGenError> {
+    WorldGenError::check_finite("persistance", persistance)
+}
+
+/// Normalizes the values between 0 and 1, 
+/// using the smallest and largest of the values.
+/// 
+/// Maybe find a math crate to make this prettier :\
+fn normalize_by_buffer(values: &mut [f64]) {
+    let max = values   // Largest value
+        .iter()
+        .fold(
+            f64::NEG_INFINITY,
+            |x, &y| x.max(y)
+    );
+
+    let min = values   // Smallest value
+        .iter()
+            .fold(
+                f64::INFINITY,
+                |x, &y| x.min(y)
+            );
+
+    for value in values.iter_mut() {
+        *value = (*value - min) / (max - min); // Calculates the normalized value
+    }
+}
+
+/// Maps the range [`min`; `max`] into [0; 1], clamping values outside the range.
+fn normalize_by_range(values: &mut [f64], min: f64, max: f64) {
+    for value in values.iter_mut() {
+        *value = ((*value - min) / (max - min)).clamp(0.0, 1.0);
+    }
+}
 
 /// A struct holding noise data for a section of the given noise function
 /// TODO:
-///     Height and width should be replaced with two points(maybe)
 ///     Possibly reformat all of this
 ///     Find an apropriate default scale (this can't be done yet)
 pub struct NoiseMap {
-    height: usize,
-    width: usize,
-    values: Vec<f64>,
+    origin: (i64, i64),     // World-space (row, column) of the first value in the map
+    values: Grid2D<f64>,
+    filled: bool,           // The values are all 0 until the map is filled
     
 // Noise modyfiers:
     scale: usize,
     octaves: usize,     // A NoiseMap contains noise built from several noise maps aka. octaves
     lacunarity: f64,    // Lacunarity defines the frequency of octaves
     persistance: f64,   // Persistance defines the amplitude of octaves
+    normalization: Normalization,
+    fractal: FractalMode,   // Defines how octaves are shaped before they are summed
+    warps: Vec<DomainWarp>, // Displace the sample point of every cell, applied in order
 }
 
 impl NoiseMap {
@@ -35,9 +184,7 @@ impl NoiseMap {
     /// their assosiated methods, before any further use of NoiseMaps created from this method
     pub fn new(height: usize, width: usize) -> Self {
         NoiseMap {
-            height,
-            width,
-            values: Vec::with_capacity(height * width),
+            values: Grid2D::new(height, width, 0.0),
             ..Default::default()
         }
     }
@@ -58,7 +205,7 @@ impl NoiseMap {
         lacunarity: f64,
         persistance: f64,
         noise_fn: impl NoiseFn<[f64; 2]>,
-        seed: u32,
+        seed: WorldSeed,
     ) -> Self {
         let mut map = NoiseMap::new(
             height,
@@ -74,6 +221,43 @@ impl NoiseMap {
         map
     }
 
+    /// Creates and fills a NoiseMap covering the given world-space [`Region`].
+    /// 
+    /// The map is normalized by [`Normalization::AmplitudeBound`], which does not depend on the map itself,
+    /// so the value of a cell only depends on its world-space position, 
+    /// and neighbouring regions (e.g. chunks) are seamless along their shared edges.
+    /// 
+    /// # Panics
+    /// Panics if `lacunarity` or `persistance` are parsed as 
+    ///     [`NAN`](`std::primitive::f64::NAN`), 
+    ///     [`INFINITY`](`std::primitive::f64::INFINITY`) 
+    ///     or 
+    ///     [`NEG_INFINITY`](`std::primitive::f64::NEG_INFINITY`).
+    pub fn from_region(
+        region: Region,
+        scale: usize,
+        octaves: usize,
+        lacunarity: f64,
+        persistance: f64,
+        noise_fn: impl NoiseFn<[f64; 2]>,
+        seed: WorldSeed,
+    ) -> Self {
+        let mut map = NoiseMap::new(
+            region.height,
+            region.width,
+        );
+        map.origin = region.origin;
+
+        map.set_scale(scale);
+        map.set_octaves(octaves);
+        map.set_lacunarity(lacunarity);
+        map.set_persistance(persistance);
+        map.set_normalization(Normalization::AmplitudeBound);
+
+        map.fill(noise_fn, seed);
+        map
+    }
+
     /// Sets the scale of the NoiseMap.
     /// 
     /// # Note
@@ -112,15 +296,20 @@ impl NoiseMap {
     /// The lacunarity can't be 0. If 0 is parsed into this method, 
     /// the lacunarity will be set to [`DEFAULT_LACUNARITY`]
     pub fn set_lacunarity(&mut self, lacunarity: f64) {
-        match lacunarity {
-            _x if _x == 0.0 => self.lacunarity = DEFAULT_LACUNARITY,
-            _x if _x.is_finite() => self.lacunarity = lacunarity,
-            _x if _x.is_nan() => panic!("NaN lacunarity"),
-            _x if _x.is_infinite() => panic!("Infinite lacunarity"),
-            _ => unreachable!(),
+        if let Err(e) = self.try_set_lacunarity(lacunarity) {
+            panic!("{}", e);
         }
     }
 
+    /// Sets the lacunarity of the NoiseMap, returning an error instead of panicking on 
+    /// [`NAN`](`std::primitive::f64::NAN`) or infinite values.
+    /// 
+    /// See [`set_lacunarity`](Self::set_lacunarity).
+    pub fn try_set_lacunarity(&mut self, lacunarity: f64) -> Result<(), WorldGenError> {
+        self.lacunarity = checked_lacunarity(lacunarity)?;
+        Ok(())
+    }
+
     /// Sets the persistance of the NoiseMap.
     /// 
     /// # Panics
@@ -134,31 +323,84 @@ impl NoiseMap {
     /// Although the persistance can be set to 0, 
     /// doing this would be equivilant to only having 1 octave.
     pub fn set_persistance(&mut self, persistance: f64) {
-        match persistance {
-            _x if _x.is_finite() => self.persistance = persistance,
-            _x if _x.is_nan() => panic!("NaN lacunarity"),
-            _x if _x.is_infinite() => panic!("Infinite lacunarity"),
-            _ => unreachable!(),
+        if let Err(e) = self.try_set_persistance(persistance) {
+            panic!("{}", e);
         }
     }
 
-    /// Changes the size of the NoiseMap.
+    /// Sets the persistance of the NoiseMap, returning an error instead of panicking on 
+    /// [`NAN`](`std::primitive::f64::NAN`) or infinite values.
     /// 
-    /// This will empty the NoiseMap.
+    /// See [`set_persistance`](Self::set_persistance).
+    pub fn try_set_persistance(&mut self, persistance: f64) -> Result<(), WorldGenError> {
+        self.persistance = checked_persistance(persistance)?;
+        Ok(())
+    }
+
+    /// Sets how the NoiseMap maps its values into [0; 1].
     /// 
-    /// (There is propably no reason for us to ever use this...)
-    pub fn _resize(&mut self, height: usize, width: usize) {
-        self.height = height;
-        self.width = width;
+    /// # Panics
+    /// Panics if a [`Fixed`](Normalization::Fixed) range is not finite, or its `min` is not smaller than its `max`.
+    pub fn set_normalization(&mut self, normalization: Normalization) {
+        if let Err(e) = self.try_set_normalization(normalization) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Sets how the NoiseMap maps its values into [0; 1], 
+    /// returning an error instead of panicking on invalid [`Fixed`](Normalization::Fixed) ranges.
+    pub fn try_set_normalization(&mut self, normalization: Normalization) -> Result<(), WorldGenError> {
+        normalization.validate()?;
+        self.normalization = normalization;
+        Ok(())
+    }
 
-        self.values = Vec::with_capacity(height * width);
+    /// Sets the [`FractalMode`] used to combine the octaves of the NoiseMap.
+    pub fn set_fractal(&mut self, fractal: FractalMode) {
+        self.fractal = fractal;
     }
 
-    /// Returns the actual index of the given row, column in the list of values.
+    /// Moves the NoiseMap to the given world-space (row, column). 
     /// 
-    /// Uses a single vec as it is faster and easier to create a buffer from.
-    fn _index(&self, row: usize, column: usize) -> usize {
-        row * self.width + column   // This lets us use a simple list of values, and read it as a 2-dimentional map
+    /// Should be used before the map is filled.
+    pub fn set_origin(&mut self, origin: (i64, i64)) {
+        self.origin = origin;
+    }
+
+    /// Adds a [`DomainWarp`] to the NoiseMap.
+    /// 
+    /// Warps are applied in the order they are added, 
+    /// every warp displacing the point already displaced by the warps before it.
+    pub fn add_domain_warp(&mut self, warp: DomainWarp) {
+        self.warps.push(warp);
+    }
+
+    /// Removes all domain warps from the NoiseMap.
+    pub fn clear_domain_warps(&mut self) {
+        self.warps.clear();
+    }
+
+    /// Sinks the values of the NoiseMap towards 0 according to the given mask.
+    /// 
+    /// Should be used after the map is filled. Every value is scaled by how much of it the mask keeps,
+    /// so cells where the mask is fully sunk end up at 0, the bottom of the deepest ocean.
+    pub fn apply_falloff(&mut self, mask: &FalloffMask) {
+        for row in 0..self.get_height() {
+            for column in 0..self.get_width() {
+                let falloff = mask.falloff(self.origin.0 + row as i64, self.origin.1 + column as i64);
+                self.values[(row, column)] *= 1.0 - falloff;
+            }
+        }
+    }
+
+    /// Changes the size of the NoiseMap.
+    /// 
+    /// This will empty the NoiseMap.
+    /// 
+    /// (There is propably no reason for us to ever use this...)
+    pub fn _resize(&mut self, height: usize, width: usize) {
+        self.values = Grid2D::new(height, width, 0.0);
+        self.filled = false;
     }
 
     /// Returns the value at the given index (the map is 0-index).
@@ -166,21 +408,57 @@ impl NoiseMap {
     /// # Panics
     /// Parsing invalid row, column values or calling this on an empty NoiseMap, will panic.
     pub fn get_value(&self, row: usize, column: usize) -> f64 {
-        assert!(!self.values.is_empty());   // Is the NoiseMap empty?
-        assert!(row <= self.height);        // Is query out of bounds wrt. Map height?
-        assert!(column <= self.width);      // Is query out of bounds wrt. Map width?
+        match self.try_get_value(row, column) {
+            Ok(value) => value,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Returns the value at the given index (the map is 0-index), 
+    /// or an error if the index is out of bounds or the NoiseMap is empty.
+    pub fn try_get_value(&self, row: usize, column: usize) -> Result<f64, WorldGenError> {
+        if !self.filled {
+            return Err(WorldGenError::EmptyMap);
+        }
 
-        self.values[row * self.width + column]
+        self.values.try_get(row, column).copied()
     }
 
     /// Returns the height of the NoiseMap.
     pub fn get_height(&self) -> usize {
-        self.height
+        self.values.get_height()
     }
 
     /// Returns the width of the NoiseMap.
     pub fn get_width(&self) -> usize {
-        self.width
+        self.values.get_width()
+    }
+
+    /// Returns the world-space [`Region`] covered by the NoiseMap.
+    pub fn get_region(&self) -> Region {
+        Region::new(self.origin, self.get_height(), self.get_width())
+    }
+
+    /// Returns the values of the NoiseMap.
+    /// 
+    /// # Errors
+    /// Returns an error if the NoiseMap is empty.
+    pub fn try_grid(&self) -> Result<&Grid2D<f64>, WorldGenError> {
+        match self.filled {
+            true => Ok(&self.values),
+            false => Err(WorldGenError::EmptyMap),
+        }
+    }
+
+    /// Returns the values of the NoiseMap, to change them in place.
+    /// 
+    /// # Errors
+    /// Returns an error if the NoiseMap is empty.
+    pub fn try_grid_mut(&mut self) -> Result<&mut Grid2D<f64>, WorldGenError> {
+        match self.filled {
+            true => Ok(&mut self.values),
+            false => Err(WorldGenError::EmptyMap),
+        }
     }
 
     /// Set the value of a single element in the map.
@@ -190,57 +468,49 @@ impl NoiseMap {
     /// 
     /// (I don't know if we need this, so maybe delete later)
     pub fn _set(&mut self, row: usize, column: usize, value: f64) {
-        assert!(!self.values.is_empty());
-        self.values[row * self.width + column] = value;
+        assert!(self.filled);
+        self.values.set(row, column, value);
     }
 
-    /// Pushes a new element into the NoiseMap.
-    /// 
-    /// Should only be used with empty NoiseMaps
-    fn push(&mut self, value: f64) {
-        self.values.push(value);
+    /// Normalizes the values of the NoiseMap between 0 and 1, 
+    /// according to the [`Normalization`] of the map.
+    fn normalize(&mut self) {
+        let range = self.fractal.range(self.amplitude_bound());
+        self.normalization.apply(self.values.as_mut_slice(), range);
     }
 
-    /// Normalizes the values of the NoiseMap between 0 and 1.
-    /// 
-    /// Maybe find a math crate to make this prettier :\
+    /// Returns the largest absolute value the octaves can sum to.
+    pub fn amplitude_bound(&self) -> f64 {
+        amplitude_bound(self.octaves, self.persistance)
+    }
+
+    /// Returns the world-space (x, y) point of the given index, displaced by all domain warps of the map.
     /// 
-    /// Also change this. Value should be between -1 and 1.
-    fn normalize(&mut self) {
-        let max = self.values   // Largest value
-            .iter()
-            .fold(
-                f64::NEG_INFINITY,
-                |x, &y| x.max(y)
-        );
+    /// The index is moved into world-space by the origin of the NoiseMap, 
+    /// so the same cell is sampled at the same point no matter which map it is part of.
+    fn world_point(&self, row: usize, column: usize) -> [f64; 2] {
+        let world_row = self.origin.0 + row as i64;
+        let world_column = self.origin.1 + column as i64;
 
-        let min = self.values   // Smallest value
+        self.warps
             .iter()
-                .fold(
-                    f64::INFINITY,
-                    |x, &y| x.min(y)
-                );
-
-        self.values = self.values
-            .iter_mut()
-            .map(|&mut x| {
-                (x - min) / (max - min) // Calculates the normalized x
-            })
-            .collect();
+            .fold(
+                [world_column as f64, world_row as f64],
+                |point, warp| warp.displace(point)
+            )
     }
 
-    /// Returns the scaled index.
+    /// Returns the scaled world-space point.
     /// 
     /// This is used for a noise functions get method
     fn noise_point(
         &self,
-        row: usize,
-        column: usize,
+        [world_x, world_y]: [f64; 2],
         frequency: f64,
         offset: (i32, i32),
     ) -> [f64; 2] {
-        let mut x = (row as f64 / self.scale as f64) * frequency;
-        let mut y = (column as f64 / self.scale as f64) * frequency;
+        let mut x = (world_x / self.scale as f64) * frequency;
+        let mut y = (world_y / self.scale as f64) * frequency;
 
         x += offset.0 as f64;
         y += offset.1 as f64;
@@ -249,96 +519,520 @@ impl NoiseMap {
     }
 
     /// Fills the NoiseMap with values from the given noise function
+    /// 
+    /// The octaves are offset based on `seed`. 
+    /// The noise function should be seeded from the same seed (see [`WorldSeed::noise_seed`]).
+    /// 
+    /// The values are normalized according to the [`Normalization`] of the map.
     pub fn fill(
         &mut self,
         noise_fn: impl NoiseFn<[f64; 2]>,
-        seed: u32,
+        seed: WorldSeed,
     ) {
-        // Generate random offsets for all octaves based on the seed
-        let mut prng: Pcg64 = Seeder::from(seed).make_rng();
+        self.sample(noise_fn, seed);
+        self.normalize();
+    }
+
+    /// Fills the NoiseMap with values from the given noise function, 
+    /// sampling bands of rows on multiple threads.
+    /// 
+    /// The result is identical to [`fill`](Self::fill).
+    pub fn par_fill(
+        &mut self,
+        noise_fn: impl NoiseFn<[f64; 2]> + Sync,
+        seed: WorldSeed,
+    ) {
+        self.par_sample(noise_fn, seed);
+        self.normalize();
+    }
+
+    /// Generates random offsets for all octaves based on the seed
+    fn octave_offsets(&self, seed: WorldSeed) -> Vec<(i32, i32)> {
+        let mut prng = seed.rng();
         let mut octave_offsets = Vec::with_capacity(self.octaves);
         
         for _ in 0..self.octaves {
             let x = prng.gen_range(-1_000_000..1_000_000);
             let y = prng.gen_range(-1_000_000..1_000_000);
-            println!("\toctave_offset{{ x: {}, y: {} }}", x, y);
             octave_offsets.push((x, y));
         }
+        octave_offsets
+    }
+
+    /// Returns the raw sum of all octaves of the noise function at the given cell.
+    fn sample_cell(
+        &self,
+        row: usize,
+        column: usize,
+        noise_fn: &impl NoiseFn<[f64; 2]>,
+        octave_offsets: &[(i32, i32)],
+    ) -> f64 {
+        let mut noise_height = 0.0;
+        let mut frequency = 1.0;
+        let mut amplitude = 1.0;
+        let mut weight = 1.0;   // Carried between octaves by some fractal modes
+        let world_point = self.world_point(row, column);
+        
+        // Samples and combines all octaves
+        for offset in octave_offsets {
+            let q_point = 
+                self.noise_point(
+                    world_point,
+                    frequency,
+                    *offset
+                );
+            
+            let value = noise_fn.get(q_point);
+            noise_height += self.fractal.octave(value, &mut weight) * amplitude;
+            
+            frequency *= self.lacunarity;   // Scale frequency with lacunarity for every octave
+            amplitude *= self.persistance;  // Scale amplitude with percistance for every octave
+        }
+        noise_height
+    }
+
+    /// Fills the NoiseMap with the raw sum of all octaves of the given noise function.
+    fn sample(
+        &mut self,
+        noise_fn: impl NoiseFn<[f64; 2]>,
+        seed: WorldSeed,
+    ) {
+        let octave_offsets = self.octave_offsets(seed);
 
         // Fill the NoiseMap
-        for column in 0..self.height {
-            for row in 0..self.width {
-                let mut noise_height = 0.0;
-                let mut frequency = 1.0;
-                let mut amplitude = 1.0;
-                
-                // Samples and combines all octaves
-                for i in 0..self.octaves {
-                    
-                    let q_point = 
-                        self.noise_point(
-                            row,
-                            column,
-                            frequency,
-                            octave_offsets[i]
-                        );
-                    
-                    let value = noise_fn.get(q_point);
-                    noise_height += value * amplitude;
-                    
-                    frequency *= self.lacunarity;   // Scale frequency with lacunarity for every octave
-                    amplitude *= self.persistance;  // Scale amplitude with percistance for every octave
-                }
-                self.push(noise_height);    // Pushes the final value to the NoiseMap
-            }
+        self.values = Grid2D::from_fn(self.get_height(), self.get_width(), |row, column| {
+            self.sample_cell(row, column, &noise_fn, &octave_offsets)
+        });
+        self.filled = true;
+    }
+
+    /// Fills the NoiseMap with the raw sum of all octaves of the given noise function, 
+    /// giving every thread its own band of rows.
+    fn par_sample(
+        &mut self,
+        noise_fn: impl NoiseFn<[f64; 2]> + Sync,
+        seed: WorldSeed,
+    ) {
+        let octave_offsets = self.octave_offsets(seed);
+        let (height, width) = self.values.size();
+        let mut values = vec![0.0; height * width];
+
+        if width > 0 {
+            values
+                .par_chunks_mut(width)
+                .enumerate()
+                .for_each(|(row, row_values)| {
+                    for (column, value) in row_values.iter_mut().enumerate() {
+                        *value = self.sample_cell(row, column, &noise_fn, &octave_offsets);
+                    }
+                });
         }
-        self.normalize();
+        self.values = Grid2D::from_vec(height, width, values).expect("one value is sampled for every cell");
+        self.filled = true;
     }
 
     /// Maps values into 8bit values. 
     /// Used as the buffer when imaging the map
-    /// 
-    /// # Panics
-    /// Panics if the NoiseMap is empty
     fn as_u8(&self) -> Vec<u8> {
-        assert!(!self.values.is_empty());
         self
             .values
             .iter()
-            .map(|&v| ((v * 256.0) as u8))
+            .map(|&v| (v.clamp(0.0, 1.0) * u8::MAX as f64).round() as u8)
             .collect()
     }
 
-    /// Creates an achromatic image in the 'noisemap_demo' folder.
+    /// Maps values into 16bit values.
+    /// Used as the buffer when exporting the map as a 16bit image
+    fn as_u16(&self) -> Vec<u16> {
+        self
+            .values
+            .iter()
+            .map(|&v| (v.clamp(0.0, 1.0) * u16::MAX as f64).round() as u16)
+            .collect()
+    }
+
+    /// Returns an achromatic image of the NoiseMap, mapping 0 to black and 1 to white.
+    /// 
+    /// This is simply to show the NoiseMap, see [`save_as_png16`](Self::save_as_png16) for keeping its precision.
+    pub fn to_image(&self) -> Result<GrayImage, WorldGenError> {
+        if !self.filled {
+            return Err(WorldGenError::EmptyMap);
+        }
+
+        Ok(ImageBuffer::from_raw(self.get_width() as u32, self.get_height() as u32, self.as_u8())
+            .expect("NoiseMap size does not match its values"))
+    }
+
+    /// Saves an achromatic image of the NoiseMap at the given path, see [`to_image`](Self::to_image).
+    pub fn save_as_img(&self, path: impl AsRef<Path>) -> Result<(), WorldGenError> {
+        self.to_image()?
+            .save(path)
+            .map_err(|e| WorldGenError::Image(e.to_string()))
+    }
+
+    /// Saves the NoiseMap as a 16bit grayscale PNG at the given path.
     /// 
-    /// This is simply to show the NoiseMap
+    /// Unlike [`save_as_img`](Self::save_as_img), this keeps enough precision to be used as a heightmap.
+    pub fn save_as_png16(&self, path: impl AsRef<Path>) -> Result<(), WorldGenError> {
+        if !self.filled {
+            return Err(WorldGenError::EmptyMap);
+        }
+
+        let img: ImageBuffer<Luma<u16>, Vec<u16>> = 
+            ImageBuffer::from_raw(self.get_width() as u32, self.get_height() as u32, self.as_u16())
+                .expect("NoiseMap size does not match its values");
+
+        img.save(path).map_err(|e| WorldGenError::Image(e.to_string()))
+    }
+
+    /// Saves the values of the NoiseMap as raw, little-endian 32bit floats, row by row.
     /// 
-    /// # Panics
-    /// Panics if the NoiseMap is empty
-    pub fn save_as_img(&self, filename: &str) -> ImageResult<()>{
-        assert!(!self.values.is_empty());
-        let path = String::from("demos/noisemap_demo/") + filename;
-        
-        image::save_buffer(
-            path,
-            &*self.as_u8(),
-            self.width as u32,
-            self.height as u32,
-            image::ColorType::L8,
-        )
+    /// The file does not contain the size of the map, so it has to be known when reading it back.
+    pub fn save_as_raw(&self, path: impl AsRef<Path>) -> Result<(), WorldGenError> {
+        if !self.filled {
+            return Err(WorldGenError::EmptyMap);
+        }
+
+        let values: Vec<f32> = self.values.iter().map(|&v| v as f32).collect();
+        write_raw(path, &values)
+    }
+
+    /// Creates a NoiseMap from a grayscale image, mapping black to 0 and white to 1.
+    /// 
+    /// Images with colour are converted to grayscale, and every bit depth is accepted.
+    pub fn from_image(path: impl AsRef<Path>) -> Result<Self, WorldGenError> {
+        let img = image::open(path)
+            .map_err(|e| WorldGenError::Image(e.to_string()))?
+            .into_luma16();
+
+        let values = img
+            .pixels()
+            .map(|pixel| pixel[0] as f64 / u16::MAX as f64)
+            .collect();
+        NoiseMap::from_grid(Grid2D::from_vec(img.height() as usize, img.width() as usize, values)?)
     }
+
+    /// Creates a NoiseMap from a file written by [`save_as_raw`](Self::save_as_raw).
+    pub fn from_raw(path: impl AsRef<Path>, height: usize, width: usize) -> Result<Self, WorldGenError> {
+        let values = read_raw(path, height * width)?
+            .into_iter()
+            .map(|v| v as f64)
+            .collect();
+        NoiseMap::from_grid(Grid2D::from_vec(height, width, values)?)
+    }
+
+    /// Creates a filled NoiseMap from values made elsewhere, e.g. by combining other maps with [`Grid2D::zip`].
+    /// 
+    /// # Errors
+    /// Returns an error if any value isn't finite.
+    pub fn from_grid(values: Grid2D<f64>) -> Result<Self, WorldGenError> {
+        for &value in values.iter() {
+            WorldGenError::check_finite("noise value", value)?;
+        }
+
+        Ok(NoiseMap {
+            values,
+            filled: true,
+            ..Default::default()
+        })
+    }
+}
+
+/// Writes the values to a file as raw, little-endian 32bit floats.
+pub(crate) fn write_raw(path: impl AsRef<Path>, values: &[f32]) -> Result<(), WorldGenError> {
+    let bytes: Vec<u8> = values
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+
+    fs::write(path, bytes).map_err(|e| WorldGenError::Io(e.to_string()))
+}
+
+/// Reads `len` raw, little-endian 32bit floats from a file.
+pub(crate) fn read_raw(path: impl AsRef<Path>, len: usize) -> Result<Vec<f32>, WorldGenError> {
+    let bytes = fs::read(path).map_err(|e| WorldGenError::Io(e.to_string()))?;
+
+    if bytes.len() != len * 4 {
+        return Err(WorldGenError::SizeMismatch { expected: len * 4, found: bytes.len() });
+    }
+
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
 }
 
 impl Default for NoiseMap {
     fn default() -> Self {
         NoiseMap {
-            height: 0,
-            width: 0,
-            values: Vec::with_capacity(0),
+            origin: (0, 0),
+            values: Grid2D::default(),
+            filled: false,
             scale: DEFAULT_SCALE,
             octaves: 1,
-            lacunarity: 1.0,
-            persistance: 1.0,
+            lacunarity: DEFAULT_LACUNARITY,
+            persistance: DEFAULT_PERSISTANCE,
+            normalization: Normalization::default(),
+            fractal: FractalMode::default(),
+            warps: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noise::Perlin;
+
+    use super::*;
+    use crate::world_gen::noise::noise_settings::{NoiseSettings, WarpSettings};
+
+    const SEED: WorldSeed = WorldSeed::from_u64(0x5EED);
+
+    fn region_map(region: Region) -> NoiseMap {
+        NoiseMap::from_region(region, 32, 4, 2.0, 0.5, Perlin::new(), SEED)
+    }
+
+    #[test]
+    fn neighbouring_chunks_match_larger_region() {
+        let whole = region_map(Region::new((-16, -16), 32, 32));
+
+        for (chunk_row, chunk_column) in [(-1, -1), (-1, 0), (0, -1), (0, 0)] {
+            let chunk = region_map(Region::chunk(chunk_row, chunk_column, 16));
+            let row_offset = (chunk_row + 1) as usize * 16;
+            let column_offset = (chunk_column + 1) as usize * 16;
+
+            for row in 0..16 {
+                for column in 0..16 {
+                    assert_eq!(
+                        chunk.get_value(row, column).to_bits(),
+                        whole.get_value(row + row_offset, column + column_offset).to_bits(),
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn quadrants_match_whole_map() {
+        let bounded = |region: Region| {
+            let mut map = NoiseMap::new(region.height, region.width);
+            map.origin = region.origin;
+            map.set_scale(100);
+            map.set_octaves(4);
+            map.set_lacunarity(2.0);
+            map.set_persistance(0.5);
+            map.set_normalization(Normalization::AmplitudeBound);
+            map.fill(Perlin::new(), SEED);
+            map
+        };
+
+        let whole = bounded(Region::new((0, 0), 256, 256));
+        for (row_offset, column_offset) in [(0, 0), (0, 128), (128, 0), (128, 128)] {
+            let quadrant = bounded(Region::new((row_offset as i64, column_offset as i64), 128, 128));
+
+            for row in 0..128 {
+                for column in 0..128 {
+                    assert_eq!(
+                        quadrant.get_value(row, column).to_bits(),
+                        whole.get_value(row + row_offset, column + column_offset).to_bits(),
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_normalization_is_region_independent() {
+        let fixed = |region: Region| {
+            let mut map = NoiseMap::new(region.height, region.width);
+            map.origin = region.origin;
+            map.set_scale(50);
+            map.set_octaves(3);
+            map.set_lacunarity(2.0);
+            map.set_persistance(0.5);
+            map.set_normalization(Normalization::Fixed { min: -1.0, max: 1.0 });
+            map.fill(Perlin::new(), SEED);
+            map
+        };
+
+        let whole = fixed(Region::new((0, 0), 64, 64));
+        let part = fixed(Region::new((32, 16), 8, 8));
+        for row in 0..8 {
+            for column in 0..8 {
+                assert_eq!(
+                    part.get_value(row, column).to_bits(),
+                    whole.get_value(row + 32, column + 16).to_bits(),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn amplitude_bound_sums_octave_amplitudes() {
+        let mut map = NoiseMap::new(1, 1);
+        map.set_octaves(4);
+        map.set_persistance(0.5);
+        assert_eq!(map.amplitude_bound(), 1.0 + 0.5 + 0.25 + 0.125);
+    }
+
+    #[test]
+    fn par_fill_matches_fill() {
+        let region = Region::new((-40, 12), 37, 81);
+        let mut serial = NoiseMap::new(region.height, region.width);
+        let mut parallel = NoiseMap::new(region.height, region.width);
+        for map in [&mut serial, &mut parallel] {
+            map.origin = region.origin;
+            map.set_scale(20);
+            map.set_octaves(4);
+            map.set_lacunarity(2.0);
+            map.set_persistance(0.5);
+        }
+
+        serial.fill(Perlin::new(), SEED);
+        parallel.par_fill(Perlin::new(), SEED);
+
+        assert_eq!(serial.values, parallel.values);
+    }
+
+    #[test]
+    fn par_fill_matches_fill_with_settings() {
+        // Warps and fractal modes are sampled per cell too, and tall and wide maps are split into bands differently
+        let settings = NoiseSettings {
+            fractal: FractalMode::Ridged,
+            warps: vec![WarpSettings { scale: 30, strength: 8.0 }],
+            ..Default::default()
+        };
+        for (height, width) in [(23, 64), (64, 23)] {
+            let mut serial = NoiseMap::new(height, width);
+            let mut parallel = NoiseMap::new(height, width);
+            for map in [&mut serial, &mut parallel] {
+                map.apply_settings(&settings, SEED).unwrap();
+            }
+
+            serial.fill(settings.source.build(SEED), SEED);
+            parallel.par_fill(settings.source.build(SEED), SEED);
+
+            let bits = |map: &NoiseMap| map.values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+            assert_eq!(bits(&serial), bits(&parallel), "fill and par_fill differ on a {}x{} map", height, width);
+        }
+    }
+
+    #[test]
+    fn fractal_modes_stay_within_their_range() {
+        for fractal in FractalMode::ALL {
+            let mut map = NoiseMap::new(64, 64);
+            map.set_scale(16);
+            map.set_octaves(4);
+            map.set_lacunarity(2.0);
+            map.set_persistance(0.5);
+            map.set_fractal(fractal);
+
+            // The raw sums, as normalizing clamps anything outside the range
+            map.par_sample(Perlin::new(), SEED);
+            let (min, max) = fractal.range(map.amplitude_bound());
+            assert!(
+                map.values.iter().all(|v| (min..=max).contains(v)),
+                "{} produced values outside [{}; {}]", fractal, min, max,
+            );
+        }
+    }
+
+    #[test]
+    fn warped_regions_match_whole_map() {
+        let warped = |region: Region| {
+            let mut map = NoiseMap::new(region.height, region.width);
+            map.origin = region.origin;
+            map.set_scale(40);
+            map.set_octaves(3);
+            map.set_lacunarity(2.0);
+            map.set_persistance(0.5);
+            map.set_normalization(Normalization::AmplitudeBound);
+            map.add_domain_warp(DomainWarp::new(80, 30.0, SEED.derive("warp")));
+            map.add_domain_warp(DomainWarp::new(20, 4.0, SEED.derive("detail warp")));
+            map.fill(Perlin::new(), SEED);
+            map
+        };
+
+        let whole = warped(Region::new((0, 0), 48, 48));
+        let part = warped(Region::new((16, 24), 32, 24));
+        for row in 0..32 {
+            for column in 0..24 {
+                assert_eq!(
+                    part.get_value(row, column).to_bits(),
+                    whole.get_value(row + 16, column + 24).to_bits(),
+                );
+            }
+        }
+
+        let unwarped = NoiseMap::from_region(Region::new((0, 0), 48, 48), 40, 3, 2.0, 0.5, Perlin::new(), SEED);
+        assert_ne!(whole.values, unwarped.values);
+    }
+
+    #[test]
+    fn invalid_parameters_are_errors() {
+        let mut map = NoiseMap::new(4, 4);
+        assert_eq!(
+            map.try_set_lacunarity(f64::NAN).map_err(|e| e.to_string()),
+            Err("lacunarity must be a finite number, got NaN".to_string()),
+        );
+        assert!(map.try_set_persistance(f64::INFINITY).is_err());
+        assert!(map.try_set_normalization(Normalization::Fixed { min: 1.0, max: -1.0 }).is_err());
+        assert_eq!(map.try_get_value(0, 0), Err(WorldGenError::EmptyMap));
+
+        map.fill(Perlin::new(), SEED);
+        assert!(map.try_get_value(3, 3).is_ok());
+        assert!(matches!(map.try_get_value(4, 0), Err(WorldGenError::OutOfBounds { .. })));
+    }
+
+    #[test]
+    fn png16_and_raw_round_trip() {
+        let map = NoiseMap::from_noisefn(24, 40, 10, 3, 2.0, 0.5, Perlin::new(), SEED);
+        let dir = std::env::temp_dir();
+        let png_path = dir.join("noise_map_png16_and_raw_round_trip.png");
+        let raw_path = dir.join("noise_map_png16_and_raw_round_trip.raw");
+
+        map.save_as_png16(&png_path).unwrap();
+        map.save_as_raw(&raw_path).unwrap();
+        let from_png = NoiseMap::from_image(&png_path).unwrap();
+        let from_raw = NoiseMap::from_raw(&raw_path, 24, 40).unwrap();
+
+        assert_eq!((from_png.get_height(), from_png.get_width()), (24, 40));
+        for row in 0..24 {
+            for column in 0..40 {
+                let value = map.get_value(row, column);
+                assert!((from_png.get_value(row, column) - value).abs() <= 0.5 / u16::MAX as f64);
+                assert_eq!(from_raw.get_value(row, column), value as f32 as f64);
+            }
+        }
+
+        let wrong_size = NoiseMap::from_raw(&raw_path, 24, 41);
+        std::fs::remove_file(&png_path).unwrap();
+        std::fs::remove_file(&raw_path).unwrap();
+        assert!(matches!(wrong_size, Err(WorldGenError::SizeMismatch { .. })));
+    }
+
+    #[test]
+    fn peaks_are_imaged_white() {
+        let map = NoiseMap::from_grid(Grid2D::from_vec(1, 2, vec![0.0, 1.0]).unwrap()).unwrap();
+        assert_eq!(map.as_u8(), vec![0, u8::MAX]);
+        assert_eq!(map.as_u16(), vec![0, u16::MAX]);
+
+        let img = map.to_image().unwrap();
+        assert_eq!(img.dimensions(), (2, 1));
+        assert_eq!(img.get_pixel(1, 0).0, [u8::MAX]);
+        assert_eq!(NoiseMap::new(2, 2).to_image().err(), Some(WorldGenError::EmptyMap));
+    }
+
+    #[test]
+    fn overlapping_regions_share_edges() {
+        let left = region_map(Region::new((0, 0), 16, 17));
+        let right = region_map(Region::new((0, 16), 16, 16));
+
+        for row in 0..16 {
+            assert_eq!(
+                left.get_value(row, 16).to_bits(),
+                right.get_value(row, 0).to_bits(),
+            );
         }
     }
 }
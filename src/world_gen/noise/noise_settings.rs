@@ -0,0 +1,260 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::world_gen::error::WorldGenError;
+use crate::world_gen::seed::WorldSeed;
+use crate::world_gen::terrain::height_map::VerticalRange;
+use super::domain_warp::DomainWarp;
+use super::fractal::FractalMode;
+use super::noise_map::{
+    NoiseMap, Normalization, Region, 
+    DEFAULT_LACUNARITY, DEFAULT_OCTAVES, DEFAULT_PERSISTANCE, DEFAULT_SCALE,
+};
+use super::noise_source::NoiseSource;
+use super::noise_volume::NoiseVolume;
+
+/// Settings for a [`DomainWarp`]. 
+/// 
+/// The seed of the warp is derived from the world seed, so it doesn't have to be stored.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WarpSettings {
+    pub scale: usize,
+    pub strength: f64,
+}
+
+/// Everything needed to generate a [`NoiseMap`], except for its region and the world seed.
+/// 
+/// Can be saved to and loaded from RON files, so world presets can live in data files.
+/// Fields missing from a file are set to their default.
+/// 
+/// `heights` isn't used by the NoiseMap itself, but is the range the noise is mapped to
+/// when it is turned into a [`HeightMap`](crate::world_gen::terrain::height_map::HeightMap).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoiseSettings {
+    pub source: NoiseSource,
+    pub scale: usize,
+    pub octaves: usize,
+    pub lacunarity: f64,
+    pub persistance: f64,
+    pub fractal: FractalMode,
+    pub normalization: Normalization,
+    pub warps: Vec<WarpSettings>,
+    pub heights: VerticalRange,
+}
+
+impl NoiseSettings {
+    /// Returns an error if any of the settings are invalid.
+    /// 
+    /// Unlike the setters of [`NoiseMap`], a scale or number of octaves of 0 is an error, 
+    /// as it is almost certainly a mistake in a config file.
+    pub fn validate(&self) -> Result<(), WorldGenError> {
+        if self.scale == 0 {
+            return Err(WorldGenError::Zero("scale"));
+        }
+        if self.octaves == 0 {
+            return Err(WorldGenError::Zero("octaves"));
+        }
+        WorldGenError::check_finite("lacunarity", self.lacunarity)?;
+        WorldGenError::check_finite("persistance", self.persistance)?;
+        self.normalization.validate()?;
+        self.heights.validate()?;
+
+        for warp in &self.warps {
+            if warp.scale == 0 {
+                return Err(WorldGenError::Zero("warp scale"));
+            }
+            WorldGenError::check_finite("warp strength", warp.strength)?;
+        }
+        Ok(())
+    }
+
+    /// Parses and validates settings written in RON.
+    pub fn from_ron(ron: &str) -> Result<Self, WorldGenError> {
+        let settings: NoiseSettings = ron::from_str(ron)
+            .map_err(|e| WorldGenError::Parse(e.to_string()))?;
+
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Returns the settings written in RON.
+    pub fn to_ron(&self) -> Result<String, WorldGenError> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| WorldGenError::Parse(e.to_string()))
+    }
+
+    /// Loads and validates settings from a RON file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, WorldGenError> {
+        let ron = fs::read_to_string(path)
+            .map_err(|e| WorldGenError::Io(e.to_string()))?;
+
+        NoiseSettings::from_ron(&ron)
+    }
+
+    /// Saves the settings to a RON file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), WorldGenError> {
+        fs::write(path, self.to_ron()?)
+            .map_err(|e| WorldGenError::Io(e.to_string()))
+    }
+}
+
+impl Default for NoiseSettings {
+    fn default() -> Self {
+        NoiseSettings {
+            source: NoiseSource::default(),
+            scale: DEFAULT_SCALE,
+            octaves: DEFAULT_OCTAVES,
+            lacunarity: DEFAULT_LACUNARITY,
+            persistance: DEFAULT_PERSISTANCE,
+            fractal: FractalMode::default(),
+            normalization: Normalization::AmplitudeBound,   // So maps of neighbouring regions line up
+            warps: Vec::new(),
+            heights: VerticalRange::default(),
+        }
+    }
+}
+
+/// Methods for creating NoiseMaps from [`NoiseSettings`].
+impl NoiseMap {
+    /// Creates and fills a NoiseMap covering the given region, as defined by the settings.
+    /// 
+    /// The noise function and every domain warp are seeded from `seed`.
+    pub fn from_settings(
+        region: Region,
+        settings: &NoiseSettings,
+        seed: WorldSeed,
+    ) -> Result<Self, WorldGenError> {
+        let mut map = NoiseMap::new(region.height, region.width);
+        map.set_origin(region.origin);
+        map.apply_settings(settings, seed)?;
+
+        map.par_fill(settings.source.build(seed), seed);
+        Ok(map)
+    }
+
+    /// Sets all noise modifiers of the NoiseMap according to the settings, 
+    /// replacing any domain warps already added.
+    pub fn apply_settings(
+        &mut self,
+        settings: &NoiseSettings,
+        seed: WorldSeed,
+    ) -> Result<(), WorldGenError> {
+        settings.validate()?;
+
+        self.set_scale(settings.scale);
+        self.set_octaves(settings.octaves);
+        self.try_set_lacunarity(settings.lacunarity)?;
+        self.try_set_persistance(settings.persistance)?;
+        self.try_set_normalization(settings.normalization)?;
+        self.set_fractal(settings.fractal);
+
+        self.clear_domain_warps();
+        for (i, warp) in settings.warps.iter().enumerate() {
+            let warp_seed = seed.derive(&format!("warp {}", i));
+            self.add_domain_warp(DomainWarp::new(warp.scale, warp.strength, warp_seed));
+        }
+        Ok(())
+    }
+}
+
+/// Methods for creating NoiseVolumes from [`NoiseSettings`].
+/// 
+/// Only the noise source and the settings shared with [`NoiseMap`] are used,
+/// the fractal mode, domain warps and heights only apply to NoiseMaps.
+impl NoiseVolume {
+    /// Creates and fills a NoiseVolume of the given (width, height, depth), starting at world-space `origin`,
+    /// as defined by the settings.
+    /// 
+    /// The noise function is seeded from `seed`.
+    pub fn from_settings(
+        origin: (i64, i64, i64),
+        (width, height, depth): (usize, usize, usize),
+        settings: &NoiseSettings,
+        seed: WorldSeed,
+    ) -> Result<Self, WorldGenError> {
+        let mut volume = NoiseVolume::new(origin, width, height, depth);
+        volume.apply_settings(settings)?;
+
+        volume.par_fill(settings.source.build(seed), seed);
+        Ok(volume)
+    }
+
+    /// Sets all noise modifiers of the NoiseVolume according to the settings.
+    pub fn apply_settings(&mut self, settings: &NoiseSettings) -> Result<(), WorldGenError> {
+        settings.validate()?;
+
+        self.set_scale(settings.scale);
+        self.set_octaves(settings.octaves);
+        self.try_set_lacunarity(settings.lacunarity)?;
+        self.try_set_persistance(settings.persistance)?;
+        self.try_set_normalization(settings.normalization)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ron_round_trip() {
+        let settings = NoiseSettings {
+            source: NoiseSource::OpenSimplex,
+            scale: 250,
+            octaves: 6,
+            lacunarity: 2.1,
+            persistance: 0.45,
+            fractal: FractalMode::Ridged,
+            normalization: Normalization::Fixed { min: -0.8, max: 0.8 },
+            warps: vec![WarpSettings { scale: 400, strength: 60.0 }],
+            heights: VerticalRange { min: -64, max: 320, sea_level: 62 },
+        };
+
+        let ron = settings.to_ron().unwrap();
+        assert_eq!(NoiseSettings::from_ron(&ron), Ok(settings));
+    }
+
+    #[test]
+    fn default_preset_is_valid() {
+        let preset = include_str!("../../../assets/world_gen/default.ron");
+        assert!(NoiseSettings::from_ron(preset).is_ok());
+    }
+
+    #[test]
+    fn missing_fields_use_defaults() {
+        let settings = NoiseSettings::from_ron("(octaves: 4)").unwrap();
+        assert_eq!(settings, NoiseSettings { octaves: 4, ..Default::default() });
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        assert_eq!(NoiseSettings::from_ron("(scale: 0)"), Err(WorldGenError::Zero("scale")));
+        assert!(matches!(NoiseSettings::from_ron("(octaves: -1)"), Err(WorldGenError::Parse(_))));
+        assert!(matches!(
+            NoiseSettings::from_ron("(normalization: fixed(min: 1.0, max: 0.0))"),
+            Err(WorldGenError::InvalidRange { .. }),
+        ));
+        assert!(matches!(
+            NoiseSettings::from_ron("(heights: (min: 0, max: 100, sea_level: 101))"),
+            Err(WorldGenError::OutOfRange { .. }),
+        ));
+    }
+
+    #[test]
+    fn from_settings_matches_setters() {
+        let settings = NoiseSettings { scale: 30, octaves: 3, lacunarity: 2.0, persistance: 0.5, ..Default::default() };
+        let region = Region::new((5, -5), 20, 30);
+        let seed = WorldSeed::from(7);
+
+        let from_settings = NoiseMap::from_settings(region, &settings, seed).unwrap();
+        let from_region = NoiseMap::from_region(region, 30, 3, 2.0, 0.5, settings.source.build(seed), seed);
+        for row in 0..20 {
+            for column in 0..30 {
+                assert_eq!(from_settings.get_value(row, column), from_region.get_value(row, column));
+            }
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt;
+use std::str::FromStr;
+
+use noise::{NoiseFn, OpenSimplex, Perlin, Seedable, SuperSimplex, Value, Worley};
+use serde::{Deserialize, Serialize};
+
+use crate::world_gen::error::WorldGenError;
+use crate::world_gen::seed::WorldSeed;
+
+/// The basis noise functions available for generating noise maps.
+/// 
+/// Can be (de)serialized and parsed from its name, so the noise function 
+/// can be picked in a config file rather than in code.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseSource {
+    #[default]
+    Perlin,
+    OpenSimplex,
+    SuperSimplex,
+    Value,
+    Worley,
+}
+
+impl NoiseSource {
+    /// All noise sources, mostly useful for demos.
+    pub const ALL: [NoiseSource; 5] = [
+        NoiseSource::Perlin,
+        NoiseSource::OpenSimplex,
+        NoiseSource::SuperSimplex,
+        NoiseSource::Value,
+        NoiseSource::Worley,
+    ];
+
+    /// Builds the noise function, seeded from the given seed.
+    pub fn build(&self, seed: WorldSeed) -> Generator {
+        let noise_seed = seed.noise_seed();
+        match self {
+            NoiseSource::Perlin => Generator::Perlin(Perlin::new().set_seed(noise_seed)),
+            NoiseSource::OpenSimplex => Generator::OpenSimplex(OpenSimplex::new().set_seed(noise_seed)),
+            NoiseSource::SuperSimplex => Generator::SuperSimplex(SuperSimplex::new().set_seed(noise_seed)),
+            NoiseSource::Value => Generator::Value(Value::new().set_seed(noise_seed)),
+            NoiseSource::Worley => Generator::Worley(Worley::new().set_seed(noise_seed)),
+        }
+    }
+
+    /// Returns the name of the noise source, as used when parsing and serializing it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            NoiseSource::Perlin => "perlin",
+            NoiseSource::OpenSimplex => "open_simplex",
+            NoiseSource::SuperSimplex => "super_simplex",
+            NoiseSource::Value => "value",
+            NoiseSource::Worley => "worley",
+        }
+    }
+}
+
+impl fmt::Display for NoiseSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for NoiseSource {
+    type Err = WorldGenError;
+
+    /// Parses the name of a noise source. Dashes and case are ignored, so "Open-Simplex" is valid.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase().replace('-', "_");
+        NoiseSource::ALL
+            .into_iter()
+            .find(|source| source.name() == name)
+            .ok_or_else(|| WorldGenError::UnknownNoiseSource(s.to_string()))
+    }
+}
+
+/// A seeded noise function, built from a [`NoiseSource`].
+#[derive(Debug, Copy, Clone)]
+pub enum Generator {
+    Perlin(Perlin),
+    OpenSimplex(OpenSimplex),
+    SuperSimplex(SuperSimplex),
+    Value(Value),
+    Worley(Worley),
+}
+
+impl NoiseFn<[f64; 2]> for Generator {
+    fn get(&self, point: [f64; 2]) -> f64 {
+        match self {
+            Generator::Perlin(noise_fn) => noise_fn.get(point),
+            Generator::OpenSimplex(noise_fn) => noise_fn.get(point),
+            Generator::SuperSimplex(noise_fn) => noise_fn.get(point),
+            Generator::Value(noise_fn) => noise_fn.get(point),
+            Generator::Worley(noise_fn) => noise_fn.get(point),
+        }
+    }
+}
+
+impl NoiseFn<[f64; 3]> for Generator {
+    fn get(&self, point: [f64; 3]) -> f64 {
+        match self {
+            Generator::Perlin(noise_fn) => noise_fn.get(point),
+            Generator::OpenSimplex(noise_fn) => noise_fn.get(point),
+            Generator::SuperSimplex(noise_fn) => noise_fn.get(point),
+            Generator::Value(noise_fn) => noise_fn.get(point),
+            Generator::Worley(noise_fn) => noise_fn.get(point),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+        for source in NoiseSource::ALL {
+            assert_eq!(source.name().parse::<NoiseSource>(), Ok(source));
+        }
+        assert_eq!("Open-Simplex".parse::<NoiseSource>(), Ok(NoiseSource::OpenSimplex));
+        assert!("simplex".parse::<NoiseSource>().is_err());
+    }
+
+    #[test]
+    fn generators_are_seeded() {
+        let point = [12.3, 45.6];
+        for source in NoiseSource::ALL {
+            let a = source.build(WorldSeed::from(1)).get(point);
+            let b = source.build(WorldSeed::from(1)).get(point);
+            let c = source.build(WorldSeed::from(2)).get(point);
+
+            assert_eq!(a, b, "{} is not reproducible", source);
+            assert_ne!(a, c, "{} ignores its seed", source);
+        }
+    }
+}
@@ -0,0 +1,367 @@
+use noise::NoiseFn;
+use rand::prelude::*;
+use rayon::prelude::*;
+
+use crate::world_gen::error::WorldGenError;
+use crate::world_gen::seed::WorldSeed;
+use super::noise_map::{
+    amplitude_bound, checked_lacunarity, checked_persistance, Normalization,
+    DEFAULT_OCTAVES, DEFAULT_SCALE,
+};
+
+/// The 3D counterpart to [`NoiseMap`](super::noise_map::NoiseMap).
+/// 
+/// Holds noise data for a box of the given noise function, indexed by (x, y, z),
+/// where y is the vertical axis. This lets terrain be defined as a density field,
+/// which (unlike a height field) can contain overhangs, arches and caves.
+pub struct NoiseVolume {
+    width: usize,   // Size along x
+    height: usize,  // Size along y
+    depth: usize,   // Size along z
+    origin: (i64, i64, i64),    // World-space (x, y, z) of the first value in the volume
+    values: Vec<f64>,
+
+// Noise modyfiers:
+    scale: usize,
+    octaves: usize,     // A NoiseVolume contains noise built from several noise volumes aka. octaves
+    lacunarity: f64,    // Lacunarity defines the frequency of octaves
+    persistance: f64,   // Persistance defines the amplitude of octaves
+    normalization: Normalization,
+}
+
+impl NoiseVolume {
+    /// Creates a new and empty NoiseVolume of the given size, starting at world-space `origin`.
+    /// 
+    /// The [`fill`](`Self::fill`) method should be used to fill the volume with values,
+    /// or use [`from_settings`](Self::from_settings) to do it all at once.
+    /// 
+    /// # Note
+    /// Note that all noise modifiers should be set with their assosiated methods,
+    /// before any further use of NoiseVolumes created from this method
+    pub fn new(origin: (i64, i64, i64), width: usize, height: usize, depth: usize) -> Self {
+        NoiseVolume {
+            width,
+            height,
+            depth,
+            origin,
+            values: Vec::with_capacity(width * height * depth),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the scale of the NoiseVolume.
+    /// 
+    /// # Note
+    /// The scale cannot be 0. If 0 is parsed into this method,
+    /// the scale will be set to [DEFAULT_SCALE]
+    pub fn set_scale(&mut self, scale: usize) {
+        match scale {
+            0 => self.scale = DEFAULT_SCALE,
+            _ => self.scale = scale,
+        }
+    }
+
+    /// Sets the octaves of the NoiseVolume.
+    /// 
+    /// # Note
+    /// If 0 is parsed into this method, the octaves will be set to [DEFAULT_OCTAVES]
+    pub fn set_octaves(&mut self, octaves: usize) {
+        match octaves {
+            0 => self.octaves = DEFAULT_OCTAVES,
+            _ => self.octaves = octaves,
+        }
+    }
+
+    /// Sets the lacunarity of the NoiseVolume
+    /// 
+    /// # Panics
+    /// Panics if the parsed lacunarity is not finite.
+    /// 
+    /// ## Note
+    /// If 0 is parsed into this method, the lacunarity will be set to [`DEFAULT_LACUNARITY`](super::noise_map::DEFAULT_LACUNARITY)
+    pub fn set_lacunarity(&mut self, lacunarity: f64) {
+        if let Err(e) = self.try_set_lacunarity(lacunarity) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Sets the lacunarity of the NoiseVolume, returning an error instead of panicking if it is not finite.
+    /// 
+    /// See [`set_lacunarity`](Self::set_lacunarity).
+    pub fn try_set_lacunarity(&mut self, lacunarity: f64) -> Result<(), WorldGenError> {
+        self.lacunarity = checked_lacunarity(lacunarity)?;
+        Ok(())
+    }
+
+    /// Sets the persistance of the NoiseVolume.
+    /// 
+    /// # Panics
+    /// Panics if the parsed persistance is not finite.
+    pub fn set_persistance(&mut self, persistance: f64) {
+        if let Err(e) = self.try_set_persistance(persistance) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Sets the persistance of the NoiseVolume, returning an error instead of panicking if it is not finite.
+    pub fn try_set_persistance(&mut self, persistance: f64) -> Result<(), WorldGenError> {
+        self.persistance = checked_persistance(persistance)?;
+        Ok(())
+    }
+
+    /// Sets how the NoiseVolume maps its values into [0; 1].
+    /// 
+    /// # Panics
+    /// Panics if a [`Fixed`](Normalization::Fixed) range is not finite, or its `min` is not smaller than its `max`.
+    pub fn set_normalization(&mut self, normalization: Normalization) {
+        if let Err(e) = self.try_set_normalization(normalization) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Sets how the NoiseVolume maps its values into [0; 1], 
+    /// returning an error instead of panicking on invalid [`Fixed`](Normalization::Fixed) ranges.
+    pub fn try_set_normalization(&mut self, normalization: Normalization) -> Result<(), WorldGenError> {
+        normalization.validate()?;
+        self.normalization = normalization;
+        Ok(())
+    }
+
+    /// Returns the actual index of the given position in the list of values.
+    /// 
+    /// Every horizontal (x, z) slice of the volume is stored contiguously.
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        assert!(x < self.width);
+        assert!(y < self.height);
+        assert!(z < self.depth);
+
+        (y * self.depth + z) * self.width + x
+    }
+
+    /// Returns the value at the given position (the volume is 0-index).
+    /// 
+    /// # Panics
+    /// Panics if the position is outside the volume, or the volume is empty.
+    pub fn get_value(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.values[ self.index(x, y, z) ]
+    }
+
+    /// Returns the size of the NoiseVolume along x.
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the size of the NoiseVolume along y.
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the size of the NoiseVolume along z.
+    pub fn get_depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the world-space (x, y, z) of the first value in the NoiseVolume.
+    pub fn get_origin(&self) -> (i64, i64, i64) {
+        self.origin
+    }
+
+    /// Returns the largest absolute value the octaves can sum to.
+    pub fn amplitude_bound(&self) -> f64 {
+        amplitude_bound(self.octaves, self.persistance)
+    }
+
+    /// Returns the scaled, world-space position of the given index.
+    /// 
+    /// This is used for a noise functions get method
+    fn noise_point(
+        &self,
+        (x, y, z): (usize, usize, usize),
+        frequency: f64,
+        offset: (i32, i32, i32),
+    ) -> [f64; 3] {
+        let world_x = self.origin.0 + x as i64;
+        let world_y = self.origin.1 + y as i64;
+        let world_z = self.origin.2 + z as i64;
+        let scale = self.scale as f64;
+
+        [
+            (world_x as f64 / scale) * frequency + offset.0 as f64,
+            (world_y as f64 / scale) * frequency + offset.1 as f64,
+            (world_z as f64 / scale) * frequency + offset.2 as f64,
+        ]
+    }
+
+    /// Generates random offsets for all octaves based on the seed
+    fn octave_offsets(&self, seed: WorldSeed) -> Vec<(i32, i32, i32)> {
+        let mut prng = seed.rng();
+
+        (0..self.octaves)
+            .map(|_| (
+                prng.gen_range(-1_000_000..1_000_000),
+                prng.gen_range(-1_000_000..1_000_000),
+                prng.gen_range(-1_000_000..1_000_000),
+            ))
+            .collect()
+    }
+
+    /// Returns the raw sum of all octaves of the noise function at the given position.
+    fn sample_cell(
+        &self,
+        position: (usize, usize, usize),
+        noise_fn: &impl NoiseFn<[f64; 3]>,
+        octave_offsets: &[(i32, i32, i32)],
+    ) -> f64 {
+        let mut density = 0.0;
+        let mut frequency = 1.0;
+        let mut amplitude = 1.0;
+
+        // Samples and combines all octaves
+        for offset in octave_offsets {
+            let q_point = self.noise_point(position, frequency, *offset);
+
+            density += noise_fn.get(q_point) * amplitude;
+
+            frequency *= self.lacunarity;   // Scale frequency with lacunarity for every octave
+            amplitude *= self.persistance;  // Scale amplitude with percistance for every octave
+        }
+        density
+    }
+
+    /// Fills the NoiseVolume with values from the given noise function
+    /// 
+    /// The octaves are offset based on `seed`.
+    /// The noise function should be seeded from the same seed (see [`WorldSeed::noise_seed`]).
+    /// 
+    /// The values are normalized according to the [`Normalization`] of the volume.
+    pub fn fill(
+        &mut self,
+        noise_fn: impl NoiseFn<[f64; 3]>,
+        seed: WorldSeed,
+    ) {
+        let octave_offsets = self.octave_offsets(seed);
+        let mut values = Vec::with_capacity(self.width * self.height * self.depth);
+
+        for y in 0..self.height {
+            for z in 0..self.depth {
+                for x in 0..self.width {
+                    values.push(self.sample_cell((x, y, z), &noise_fn, &octave_offsets));
+                }
+            }
+        }
+        self.values = values;
+        self.normalize();
+    }
+
+    /// Fills the NoiseVolume with values from the given noise function,
+    /// sampling every horizontal slice on its own thread.
+    /// 
+    /// The result is identical to [`fill`](Self::fill).
+    pub fn par_fill(
+        &mut self,
+        noise_fn: impl NoiseFn<[f64; 3]> + Sync,
+        seed: WorldSeed,
+    ) {
+        let octave_offsets = self.octave_offsets(seed);
+        let mut values = vec![0.0; self.width * self.height * self.depth];
+        let slice_size = self.width * self.depth;
+
+        if slice_size > 0 {
+            values
+                .par_chunks_mut(slice_size)
+                .enumerate()
+                .for_each(|(y, slice)| {
+                    for (i, value) in slice.iter_mut().enumerate() {
+                        let (z, x) = (i / self.width, i % self.width);
+                        *value = self.sample_cell((x, y, z), &noise_fn, &octave_offsets);
+                    }
+                });
+        }
+        self.values = values;
+        self.normalize();
+    }
+
+    /// Normalizes the values of the NoiseVolume between 0 and 1,
+    /// according to the [`Normalization`] of the volume.
+    fn normalize(&mut self) {
+        let bound = self.amplitude_bound();
+        self.normalization.apply(&mut self.values, (-bound, bound));
+    }
+}
+
+impl Default for NoiseVolume {
+    fn default() -> Self {
+        NoiseVolume {
+            width: 0,
+            height: 0,
+            depth: 0,
+            origin: (0, 0, 0),
+            values: Vec::with_capacity(0),
+            scale: DEFAULT_SCALE,
+            octaves: 1,
+            lacunarity: 1.0,
+            persistance: 1.0,
+            normalization: Normalization::AmplitudeBound,   // So neighbouring volumes line up
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_gen::noise::noise_map::DEFAULT_LACUNARITY;
+    use crate::world_gen::noise::noise_settings::NoiseSettings;
+
+    const SEED: WorldSeed = WorldSeed::from_u64(0x5EED);
+
+    fn settings() -> NoiseSettings {
+        NoiseSettings { scale: 16, octaves: 3, lacunarity: 2.0, persistance: 0.5, ..Default::default() }
+    }
+
+    fn volume(origin: (i64, i64, i64), size: (usize, usize, usize)) -> NoiseVolume {
+        NoiseVolume::from_settings(origin, size, &settings(), SEED).unwrap()
+    }
+
+    #[test]
+    fn neighbouring_volumes_match_larger_volume() {
+        let whole = volume((0, -8, 0), (16, 16, 16));
+        let part = volume((8, 0, 4), (8, 8, 8));
+
+        for y in 0..8 {
+            for z in 0..8 {
+                for x in 0..8 {
+                    assert_eq!(
+                        part.get_value(x, y, z).to_bits(),
+                        whole.get_value(x + 8, y + 8, z + 4).to_bits(),
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn par_fill_matches_fill() {
+        let parallel = volume((-3, 5, 7), (9, 6, 11));
+        let mut serial = NoiseVolume::new((-3, 5, 7), 9, 6, 11);
+        serial.apply_settings(&settings()).unwrap();
+        serial.fill(settings().source.build(SEED), SEED);
+
+        assert_eq!(serial.values, parallel.values);
+    }
+
+    #[test]
+    fn values_are_normalized() {
+        let volume = volume((0, 0, 0), (8, 8, 8));
+        assert!(volume.values.iter().all(|v| (0.0..=1.0).contains(v)));
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        let mut volume = NoiseVolume::new((0, 0, 0), 1, 1, 1);
+        assert!(volume.try_set_lacunarity(f64::NAN).is_err());
+        assert!(volume.try_set_persistance(f64::INFINITY).is_err());
+
+        volume.set_lacunarity(0.0);
+        assert_eq!(volume.lacunarity, DEFAULT_LACUNARITY);
+    }
+}
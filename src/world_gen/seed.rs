@@ -0,0 +1,94 @@
+use std::fmt;
+
+use rand::prelude::*;
+use rand_seeder::Seeder;   // Seeder is not cryptographically safe, but that does not matter for us
+use rand_pcg::Pcg64;
+
+/// The seed of a world.
+/// 
+/// Every noise function and random number generator used to generate a world should be seeded from this,
+/// so changing the seed changes the whole world, and using the same seed reproduces it exactly.
+/// 
+/// Different parts of world generation should use [`derive`](Self::derive) to get their own seed, 
+/// so they don't end up generating the exact same values.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct WorldSeed(u64);
+
+impl WorldSeed {
+    /// Creates a WorldSeed from a number.
+    pub const fn from_u64(seed: u64) -> Self {
+        WorldSeed(seed)
+    }
+
+    /// Returns the number behind the WorldSeed.
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+
+    /// Returns a new seed, derived from this seed and the given label.
+    /// 
+    /// The same seed and label always result in the same derived seed.
+    pub fn derive(&self, label: &str) -> WorldSeed {
+        let mut prng: Pcg64 = Seeder::from((self.0, label)).make_rng();
+        WorldSeed(prng.gen())
+    }
+
+    /// Returns the seed used by noise functions, which only accept 32 bit seeds.
+    pub fn noise_seed(&self) -> u32 {
+        self.derive("noise").0 as u32
+    }
+
+    /// Returns a random number generator seeded by this seed.
+    pub fn rng(&self) -> Pcg64 {
+        Seeder::from(self.0).make_rng()
+    }
+}
+
+impl From<u64> for WorldSeed {
+    fn from(seed: u64) -> Self {
+        WorldSeed(seed)
+    }
+}
+
+/// Strings containing a number are read as that number, 
+/// any other string is hashed into a seed.
+impl From<&str> for WorldSeed {
+    fn from(seed: &str) -> Self {
+        match seed.trim().parse::<u64>() {
+            Ok(number) => WorldSeed(number),
+            Err(_) => {
+                let mut prng: Pcg64 = Seeder::from(seed).make_rng();
+                WorldSeed(prng.gen())
+            }
+        }
+    }
+}
+
+impl fmt::Display for WorldSeed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numeric_strings_are_read_as_numbers() {
+        assert_eq!(WorldSeed::from("24301"), WorldSeed::from(24301));
+    }
+
+    #[test]
+    fn string_seeds_are_reproducible() {
+        assert_eq!(WorldSeed::from("minecraft"), WorldSeed::from("minecraft"));
+        assert_ne!(WorldSeed::from("minecraft"), WorldSeed::from("Minecraft"));
+    }
+
+    #[test]
+    fn derived_seeds_depend_on_label() {
+        let seed = WorldSeed::from(0x5EED);
+        assert_eq!(seed.derive("temperature"), seed.derive("temperature"));
+        assert_ne!(seed.derive("temperature"), seed.derive("humidity"));
+    }
+}
@@ -1,3 +1,10 @@
+pub mod biome;
+pub mod erosion;
 pub mod texture;
 pub mod terrain_type;
 pub mod height_map;
+pub mod hydrology;
+pub mod render;
+pub mod shaping;
+pub mod spline;
+pub mod surface;
@@ -0,0 +1,354 @@
+use std::fmt;
+
+use image::Rgb;
+use serde::{Deserialize, Serialize};
+
+use crate::world_gen::error::WorldGenError;
+use crate::world_gen::grid::Grid2D;
+use crate::world_gen::noise::noise_map::{NoiseMap, Normalization, Region};
+use crate::world_gen::noise::noise_settings::NoiseSettings;
+use crate::world_gen::seed::WorldSeed;
+use super::height_map::HeightMap;
+use super::terrain_type::{TerrainRegistry, TerrainType};
+
+/// All the different biomes.
+///
+/// Land biomes are picked from the climate of a cell (see [`Biome::classify`]),
+/// everything else follows the [`TerrainType`] of the cell.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Biome {
+    Ocean,
+    Beach,
+    Freshwater,
+    Desert,
+    Savanna,
+    Jungle,
+    Grassland,
+    Forest,
+    Swamp,
+    Taiga,
+    Tundra,
+    Snow,
+}
+
+/// Whittaker-style lookup table of land biomes.
+///
+/// Rows go from cold to hot, and columns from dry to wet.
+const WHITTAKER: [[Biome; 4]; 4] = {
+    use Biome::*;
+    [
+        [Tundra,    Tundra,    Taiga,  Taiga],
+        [Grassland, Grassland, Forest, Taiga],
+        [Desert,    Grassland, Forest, Forest],
+        [Desert,    Savanna,   Jungle, Jungle],
+    ]
+};
+
+impl Biome {
+    /// Picks the land biome of a cell from its climate.
+    ///
+    /// `temperature` and `humidity` are in [0; 1], and `altitude` is how far above sea level the cell is, in [0; 1].
+    /// The higher up a cell is, the colder it gets (scaled by `lapse_rate`), so high and cold mountains are covered in snow,
+    /// and wet, warm cells close to sea level turn into swamps.
+    pub fn classify(temperature: f64, humidity: f64, altitude: f64, lapse_rate: f64) -> Biome {
+        let temperature = temperature - altitude * lapse_rate;
+        if temperature < 0.1 && altitude > 0.2 {
+            return Biome::Snow;
+        }
+        if humidity > 0.75 && temperature > 0.5 && altitude < 0.1 {
+            return Biome::Swamp;
+        }
+
+        let band = |value: f64| ((value * 4.0).floor().max(0.0) as usize).min(3);
+        WHITTAKER[band(temperature)][band(humidity)]
+    }
+
+    /// Returns the name of the biome, as written in data files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Biome::Ocean      => "ocean",
+            Biome::Beach      => "beach",
+            Biome::Freshwater => "freshwater",
+            Biome::Desert     => "desert",
+            Biome::Savanna    => "savanna",
+            Biome::Jungle     => "jungle",
+            Biome::Grassland  => "grassland",
+            Biome::Forest     => "forest",
+            Biome::Swamp      => "swamp",
+            Biome::Taiga      => "taiga",
+            Biome::Tundra     => "tundra",
+            Biome::Snow       => "snow",
+        }
+    }
+
+    /// Returns the assosiated colour of the biome.
+    pub fn colour(&self) -> Rgb<u8> {
+        match self {
+            Biome::Ocean      => Rgb([ 65, 105, 225]),
+            Biome::Beach      => Rgb([194, 178, 128]),
+            Biome::Freshwater => Rgb([ 64, 164, 223]),
+            Biome::Desert     => Rgb([237, 201, 115]),
+            Biome::Savanna    => Rgb([177, 167,  72]),
+            Biome::Jungle     => Rgb([ 20,  92,  30]),
+            Biome::Grassland  => Rgb([124, 176,  68]),
+            Biome::Forest     => Rgb([ 34, 120,  45]),
+            Biome::Swamp      => Rgb([ 72,  94,  60]),
+            Biome::Taiga      => Rgb([ 53,  94,  80]),
+            Biome::Tundra     => Rgb([150, 155, 130]),
+            Biome::Snow       => Rgb([240, 245, 250]),
+        }
+    }
+}
+
+impl fmt::Display for Biome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Everything needed to generate the climate of a region, except for the world seed.
+///
+/// Temperature and humidity get their own seeds, derived from the world seed,
+/// so they don't line up with each other or the terrain.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClimateSettings {
+    pub temperature: NoiseSettings,
+    pub humidity: NoiseSettings,
+    pub lapse_rate: f64,        // How much colder it gets from sea level to the top of the world
+}
+
+impl ClimateSettings {
+    /// Returns an error if any of the settings are invalid.
+    pub fn validate(&self) -> Result<(), WorldGenError> {
+        self.temperature.validate()?;
+        self.humidity.validate()?;
+        WorldGenError::check_finite("lapse rate", self.lapse_rate)?;
+        Ok(())
+    }
+}
+
+impl Default for ClimateSettings {
+    fn default() -> Self {
+        // Climate changes slowly, and the extremes should be just as common as the middle
+        let climate = NoiseSettings {
+            octaves: 3,
+            lacunarity: 2.0,
+            persistance: 0.5,
+            normalization: Normalization::Fixed { min: -0.8, max: 0.8 },
+            ..Default::default()
+        };
+
+        ClimateSettings {
+            temperature: NoiseSettings { scale: 600, ..climate.clone() },
+            humidity: NoiseSettings { scale: 400, ..climate },
+            lapse_rate: 0.5,
+        }
+    }
+}
+
+/// The biome of every cell in a [`HeightMap`].
+///
+/// Indexed the same way as the HeightMap it was made from.
+pub struct BiomeMap {
+    values: Grid2D<Biome>,
+}
+
+impl BiomeMap {
+    /// Creates a BiomeMap from the given HeightMap and climate.
+    ///
+    /// Water and beaches get their biome from their [`TerrainType`],
+    /// land is classified by its temperature, humidity and how far up it is between sea level and the top of the HeightMap's range.
+    ///
+    /// # Errors
+    /// Returns an error if the maps are not the same size, or a height isn't covered by any class in the registry.
+    pub fn new(
+        height_map: &HeightMap,
+        registry: &TerrainRegistry,
+        temperature: &NoiseMap,
+        humidity: &NoiseMap,
+        settings: &ClimateSettings,
+    ) -> Result<Self, WorldGenError> {
+        let (height, width) = (height_map.get_height(), height_map.get_width());
+        for map in [temperature, humidity] {
+            if (map.get_height(), map.get_width()) != (height, width) {
+                return Err(WorldGenError::DimensionMismatch {
+                    expected: (height, width),
+                    found: (map.get_height(), map.get_width()),
+                });
+            }
+        }
+        settings.validate()?;
+
+        let range = height_map.get_range();
+        let land_range = (range.max - range.sea_level).max(1) as f64;
+        let mut values = Vec::with_capacity(height * width);
+        for row in 0..height {
+            for column in 0..width {
+                let biome = match height_map.terrain(registry, row, column)?.kind {
+                    TerrainType::DeepOcean | TerrainType::Ocean => Biome::Ocean,
+                    TerrainType::Beach => Biome::Beach,
+                    TerrainType::River | TerrainType::Lake => Biome::Freshwater,
+                    TerrainType::LowLand | TerrainType::HighLand => {
+                        let altitude = (height_map.get(row, column) - range.sea_level) as f64 / land_range;
+                        Biome::classify(
+                            temperature.try_get_value(row, column)?,
+                            humidity.try_get_value(row, column)?,
+                            altitude.clamp(0.0, 1.0),
+                            settings.lapse_rate,
+                        )
+                    }
+                };
+                values.push(biome);
+            }
+        }
+
+        Ok(BiomeMap {
+            values: Grid2D::from_vec(height, width, values)?,
+        })
+    }
+
+    /// Generates the climate of the region covered by the HeightMap, and creates a BiomeMap from it.
+    pub fn generate(
+        height_map: &HeightMap,
+        registry: &TerrainRegistry,
+        origin: (i64, i64),
+        settings: &ClimateSettings,
+        seed: WorldSeed,
+    ) -> Result<Self, WorldGenError> {
+        let region = Region::new(origin, height_map.get_height(), height_map.get_width());
+        let temperature = NoiseMap::from_settings(region, &settings.temperature, seed.derive("temperature"))?;
+        let humidity = NoiseMap::from_settings(region, &settings.humidity, seed.derive("humidity"))?;
+
+        BiomeMap::new(height_map, registry, &temperature, &humidity, settings)
+    }
+
+    /// Returns the biome at the given position
+    pub fn get(&self, row: usize, column: usize) -> Biome {
+        self.values[(row, column)]
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.values.get_height()
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.values.get_width()
+    }
+
+    /// Returns the biomes of the BiomeMap.
+    pub fn grid(&self) -> &Grid2D<Biome> {
+        &self.values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noise::Perlin;
+
+    use super::*;
+    use crate::world_gen::terrain::height_map::VerticalRange;
+    use crate::world_gen::terrain::terrain_type::TerrainClass;
+
+    const SEED: WorldSeed = WorldSeed::from_u64(0x5EED);
+
+    fn height_map() -> HeightMap {
+        let n_map = NoiseMap::from_noisefn(64, 64, 30, 4, 2.0, 0.5, Perlin::new(), SEED);
+        let range = VerticalRange::default();
+        HeightMap::from_noise_map(&n_map, range, |v| range.height_at(v))
+    }
+
+    #[test]
+    fn whittaker_corners() {
+        assert_eq!(Biome::classify(0.95, 0.05, 0.0, 0.5), Biome::Desert);
+        assert_eq!(Biome::classify(0.95, 0.6, 0.3, 0.5), Biome::Jungle);
+        assert_eq!(Biome::classify(0.2, 0.05, 0.0, 0.5), Biome::Tundra);
+        assert_eq!(Biome::classify(0.2, 0.95, 0.0, 0.5), Biome::Taiga);
+        assert_eq!(Biome::classify(0.7, 0.9, 0.05, 0.5), Biome::Swamp);
+    }
+
+    #[test]
+    fn mountains_are_colder() {
+        assert_eq!(Biome::classify(0.6, 0.6, 0.0, 0.5), Biome::Forest);
+        assert_eq!(Biome::classify(0.6, 0.6, 0.8, 0.5), Biome::Taiga);
+        assert_eq!(Biome::classify(0.55, 0.6, 1.0, 0.5), Biome::Snow);
+    }
+
+    #[test]
+    fn water_and_beaches_follow_terrain() {
+        let height_map = height_map();
+        let registry = TerrainRegistry::default();
+        let biomes = BiomeMap::generate(&height_map, &registry, (0, 0), &ClimateSettings::default(), SEED).unwrap();
+
+        for row in 0..height_map.get_height() {
+            for column in 0..height_map.get_width() {
+                let biome = biomes.get(row, column);
+                match height_map.terrain(&registry, row, column).unwrap().kind {
+                    TerrainType::DeepOcean | TerrainType::Ocean => assert_eq!(biome, Biome::Ocean),
+                    TerrainType::Beach => assert_eq!(biome, Biome::Beach),
+                    _ => assert!(!matches!(biome, Biome::Ocean | Biome::Beach | Biome::Freshwater)),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn terrain_is_identified_in_its_biome() {
+        let height_map = height_map();
+        let mut registry = TerrainRegistry::default();
+        let biomes = BiomeMap::generate(&height_map, &registry, (0, 0), &ClimateSettings::default(), SEED).unwrap();
+
+        // A class covering all land, but only in the biome of the first land cell
+        let (row, column) = (0..64 * 64)
+            .map(|i| (i / 64, i % 64))
+            .find(|&(row, column)| height_map.terrain(&registry, row, column).unwrap().kind == TerrainType::LowLand)
+            .expect("the map has some land");
+        let biome = biomes.get(row, column);
+        registry.classes.push(TerrainClass {
+            name: "special".to_string(),
+            kind: TerrainType::LowLand,
+            min: Some(5),
+            max: None,
+            colour: [255, 0, 255],
+            top_colour: None,
+            biomes: vec![biome],
+        });
+
+        assert_eq!(height_map.terrain_in(&registry, row, column, &biomes).unwrap().name, "special");
+        assert_ne!(height_map.terrain(&registry, row, column).unwrap().name, "special");
+        for row in 0..height_map.get_height() {
+            for column in 0..height_map.get_width() {
+                if biomes.get(row, column) != biome {
+                    assert_eq!(
+                        height_map.terrain_in(&registry, row, column, &biomes),
+                        height_map.terrain(&registry, row, column),
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn climate_is_independent_of_terrain() {
+        let settings = ClimateSettings::default();
+        let region = Region::new((0, 0), 8, 8);
+        let temperature = NoiseMap::from_settings(region, &settings.temperature, SEED.derive("temperature")).unwrap();
+        let humidity = NoiseMap::from_settings(region, &settings.humidity, SEED.derive("humidity")).unwrap();
+        let terrain = NoiseMap::from_settings(region, &settings.temperature, SEED).unwrap();
+
+        assert_ne!(temperature.get_value(3, 3), humidity.get_value(3, 3));
+        assert_ne!(temperature.get_value(3, 3), terrain.get_value(3, 3));
+    }
+
+    #[test]
+    fn mismatched_sizes_are_rejected() {
+        let settings = ClimateSettings::default();
+        let climate = NoiseMap::from_settings(Region::new((0, 0), 32, 64), &settings.temperature, SEED).unwrap();
+
+        assert!(matches!(
+            BiomeMap::new(&height_map(), &TerrainRegistry::default(), &climate, &climate, &settings),
+            Err(WorldGenError::DimensionMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,7 @@
+//! Passes that wear down the terrain after it has been generated.
+//! 
+//! They work on the floating point values of a [`NoiseMap`](crate::world_gen::noise::noise_map::NoiseMap), before they are mapped to integer heights,
+//! since the small changes made by every step would otherwise be rounded away.
+
+pub mod hydraulic;
+pub mod thermal;
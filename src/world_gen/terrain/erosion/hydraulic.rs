@@ -0,0 +1,301 @@
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::world_gen::error::WorldGenError;
+use crate::world_gen::grid::Grid2D;
+use crate::world_gen::noise::noise_map::NoiseMap;
+use crate::world_gen::seed::WorldSeed;
+
+/// Particle based hydraulic erosion.
+/// 
+/// Drops of water are spawned at random positions on the map and roll downhill,
+/// picking up sediment where they speed up and dropping it again where they slow down or evaporate.
+/// This carves valleys and ridgelines into the terrain, and leaves sediment fans where the slope flattens out.
+/// 
+/// Can be loaded from RON like [`NoiseSettings`](crate::world_gen::NoiseSettings), 
+/// fields missing from a file are set to their default.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HydraulicErosion {
+    pub droplets: usize,        // Number of drops simulated
+    pub lifetime: usize,        // Max number of steps a drop takes before it's gone
+    pub inertia: f64,           // How much a drop keeps its direction instead of following the slope, in [0; 1]
+    pub capacity: f64,          // How much sediment a drop can carry, relative to its speed, water and slope
+    pub min_capacity: f64,      // Lets drops keep eroding on (almost) flat terrain
+    pub erosion_rate: f64,      // How much of its free capacity a drop picks up every step, in [0; 1]
+    pub deposition_rate: f64,   // How much of its surplus sediment a drop drops every step, in [0; 1]
+    pub evaporation_rate: f64,  // How much of its water a drop loses every step, in [0; 1]
+    pub gravity: f64,
+    pub radius: usize,          // Radius of the area a drop erodes
+}
+
+impl HydraulicErosion {
+    /// Returns an error if any of the settings are invalid.
+    pub fn validate(&self) -> Result<(), WorldGenError> {
+        if self.radius == 0 {
+            return Err(WorldGenError::Zero("erosion radius"));
+        }
+        WorldGenError::check_range("inertia", self.inertia, 0.0, 1.0)?;
+        WorldGenError::check_range("erosion rate", self.erosion_rate, 0.0, 1.0)?;
+        WorldGenError::check_range("deposition rate", self.deposition_rate, 0.0, 1.0)?;
+        WorldGenError::check_range("evaporation rate", self.evaporation_rate, 0.0, 1.0)?;
+        WorldGenError::check_finite("capacity", self.capacity)?;
+        WorldGenError::check_finite("min capacity", self.min_capacity)?;
+        WorldGenError::check_finite("gravity", self.gravity)?;
+        Ok(())
+    }
+
+    /// Erodes the NoiseMap in place.
+    /// 
+    /// The drops are placed based on `seed`, so the same seed and settings always erode a map the same way.
+    /// Drops rolling off the edge of the map take their sediment with them.
+    pub fn erode(&self, map: &mut NoiseMap, seed: WorldSeed) -> Result<(), WorldGenError> {
+        self.validate()?;
+        let mut surface = Surface { values: map.try_grid_mut()? };
+
+        let (height, width) = surface.values.size();
+        if height < 2 || width < 2 {
+            return Ok(());  // There is no slope for the water to follow
+        }
+
+        let mut prng = seed.derive("hydraulic erosion").rng();
+
+        for _ in 0..self.droplets {
+            let start = (
+                prng.gen_range(0.0..(width - 1) as f64),
+                prng.gen_range(0.0..(height - 1) as f64),
+            );
+            self.simulate_droplet(&mut surface, start);
+        }
+        Ok(())
+    }
+
+    /// Rolls a single drop of water from the given (x, y) position, until it evaporates or leaves the map.
+    /// 
+    /// Returns the speed of the drop when it's gone.
+    fn simulate_droplet(&self, surface: &mut Surface, (mut x, mut y): (f64, f64)) -> f64 {
+        let (mut dir_x, mut dir_y) = (0.0, 0.0);
+        let mut speed = 1.0;
+        let mut water = 1.0;
+        let mut sediment = 0.0;
+
+        for _ in 0..self.lifetime {
+            let (old_x, old_y) = (x, y);
+            let (old_height, (gradient_x, gradient_y)) = surface.height_and_gradient(x, y);
+
+            // The drop turns towards the steepest way down, but keeps some of its direction
+            dir_x = dir_x * self.inertia - gradient_x * (1.0 - self.inertia);
+            dir_y = dir_y * self.inertia - gradient_y * (1.0 - self.inertia);
+            let length = (dir_x * dir_x + dir_y * dir_y).sqrt();
+            if length == 0.0 {
+                break;  // Stuck in a perfectly flat spot
+            }
+            dir_x /= length;
+            dir_y /= length;
+
+            x += dir_x;
+            y += dir_y;
+            if !surface.contains(x, y) {
+                break;
+            }
+
+            let height_change = surface.height_and_gradient(x, y).0 - old_height;
+            let capacity = (-height_change * speed * water * self.capacity).max(self.min_capacity);
+
+            if sediment > capacity || height_change > 0.0 {
+                // Going uphill the drop fills the pit behind it, otherwise it drops its surplus
+                let deposit = match height_change > 0.0 {
+                    true => height_change.min(sediment),
+                    false => (sediment - capacity) * self.deposition_rate,
+                };
+                sediment -= deposit;
+                surface.deposit(old_x, old_y, deposit);
+            } else {
+                // Never erode deeper than the height the drop just fell, or it digs holes
+                let erosion = ((capacity - sediment) * self.erosion_rate).min(-height_change);
+                sediment += surface.erode(old_x, old_y, erosion, self.radius);
+            }
+
+            // Falling (a negative height change) speeds the drop up, climbing slows it down
+            speed = (speed * speed - height_change * self.gravity).max(0.0).sqrt();
+            water *= 1.0 - self.evaporation_rate;
+        }
+        speed
+    }
+}
+
+impl Default for HydraulicErosion {
+    fn default() -> Self {
+        HydraulicErosion {
+            droplets: 70_000,
+            lifetime: 30,
+            inertia: 0.05,
+            capacity: 4.0,
+            min_capacity: 0.01,
+            erosion_rate: 0.3,
+            deposition_rate: 0.3,
+            evaporation_rate: 0.01,
+            gravity: 4.0,
+            radius: 3,
+        }
+    }
+}
+
+/// The values of a NoiseMap, looked up by floating point (x, y) positions, 
+/// where x is the column and y is the row.
+struct Surface<'a> {
+    values: &'a mut Grid2D<f64>,
+}
+
+impl Surface<'_> {
+    /// Returns true if the 4 cells around the position are all inside the grid.
+    fn contains(&self, x: f64, y: f64) -> bool {
+        let (height, width) = self.values.size();
+        x >= 0.0 && y >= 0.0 && x < (width - 1) as f64 && y < (height - 1) as f64
+    }
+
+    /// Returns the (row, column) of the 4 cells around the position, and how much each of them counts (bilinear weights).
+    fn corners(&self, x: f64, y: f64) -> [((usize, usize), f64); 4] {
+        let (column, row) = (x.floor() as usize, y.floor() as usize);
+        let (u, v) = (x - column as f64, y - row as f64);
+
+        [
+            ((row, column), (1.0 - u) * (1.0 - v)),
+            ((row, column + 1), u * (1.0 - v)),
+            ((row + 1, column), (1.0 - u) * v),
+            ((row + 1, column + 1), u * v),
+        ]
+    }
+
+    /// Returns the interpolated height and (x, y) gradient at the position.
+    fn height_and_gradient(&self, x: f64, y: f64) -> (f64, (f64, f64)) {
+        let [nw, ne, sw, se] = self.corners(x, y).map(|(cell, _)| self.values[cell]);
+        let (u, v) = (x - x.floor(), y - y.floor());
+
+        let height = nw * (1.0 - u) * (1.0 - v) + ne * u * (1.0 - v) + sw * (1.0 - u) * v + se * u * v;
+        let gradient_x = (ne - nw) * (1.0 - v) + (se - sw) * v;
+        let gradient_y = (sw - nw) * (1.0 - u) + (se - ne) * u;
+
+        (height, (gradient_x, gradient_y))
+    }
+
+    /// Adds `amount` of sediment to the 4 cells around the position.
+    fn deposit(&mut self, x: f64, y: f64, amount: f64) {
+        for (cell, weight) in self.corners(x, y) {
+            self.values[cell] += amount * weight;
+        }
+    }
+
+    /// Removes up to `amount` from the cells within `radius` of the position,
+    /// weighted by how close they are to it.
+    /// 
+    /// Returns the amount actually removed, as no cell is eroded below 0.
+    fn erode(&mut self, x: f64, y: f64, amount: f64, radius: usize) -> f64 {
+        let (height, width) = self.values.size();
+        let radius = radius as f64;
+        let min_column = (x - radius).ceil().max(0.0) as usize;
+        let min_row = (y - radius).ceil().max(0.0) as usize;
+        let max_column = ((x + radius).floor() as usize).min(width - 1);
+        let max_row = ((y + radius).floor() as usize).min(height - 1);
+
+        let mut cells = Vec::new();
+        for row in min_row..=max_row {
+            for column in min_column..=max_column {
+                let distance = ((column as f64 - x).powi(2) + (row as f64 - y).powi(2)).sqrt();
+                if distance < radius {
+                    cells.push(((row, column), radius - distance));
+                }
+            }
+        }
+        let total_weight: f64 = cells.iter().map(|(_, weight)| weight).sum();
+
+        let mut eroded = 0.0;
+        for (cell, weight) in cells {
+            let removed = (amount * weight / total_weight).min(self.values[cell].max(0.0));
+            self.values[cell] -= removed;
+            eroded += removed;
+        }
+        eroded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noise::Perlin;
+
+    use super::*;
+
+    const SEED: WorldSeed = WorldSeed::from_u64(0x5EED);
+
+    fn map() -> NoiseMap {
+        NoiseMap::from_noisefn(64, 48, 20, 4, 2.0, 0.5, Perlin::new(), SEED)
+    }
+
+    fn erosion() -> HydraulicErosion {
+        HydraulicErosion { droplets: 2_000, ..Default::default() }
+    }
+
+    fn values(map: &NoiseMap) -> Vec<f64> {
+        (0..map.get_height())
+            .flat_map(|row| (0..map.get_width()).map(move |column| (row, column)))
+            .map(|(row, column)| map.get_value(row, column))
+            .collect()
+    }
+
+    #[test]
+    fn drops_speed_up_downhill() {
+        // A plain slope down to the east
+        let slope = Grid2D::from_fn(8, 20, |_, column| 1.0 - column as f64 * 0.04);
+        let mut map = NoiseMap::from_grid(slope).unwrap();
+        let mut surface = Surface { values: map.try_grid_mut().unwrap() };
+
+        let erosion = HydraulicErosion { lifetime: 10, ..Default::default() };
+        let speed = erosion.simulate_droplet(&mut surface, (2.0, 3.5));
+        assert!(speed > 1.0, "the drop slowed down to {} rolling downhill", speed);
+    }
+
+    #[test]
+    fn erosion_is_deterministic() {
+        let (mut first, mut second) = (map(), map());
+        erosion().erode(&mut first, SEED).unwrap();
+        erosion().erode(&mut second, SEED).unwrap();
+
+        assert_eq!(values(&first), values(&second));
+    }
+
+    #[test]
+    fn erosion_changes_the_terrain() {
+        let before = map();
+        let mut after = map();
+        erosion().erode(&mut after, SEED).unwrap();
+
+        let mut other_seed = map();
+        erosion().erode(&mut other_seed, WorldSeed::from_u64(1)).unwrap();
+
+        assert_ne!(values(&before), values(&after));
+        assert_ne!(values(&after), values(&other_seed));
+        assert!(values(&after).iter().all(|v| v.is_finite() && *v >= 0.0));
+    }
+
+    #[test]
+    fn erosion_does_not_add_material() {
+        let before = map();
+        let mut after = map();
+        erosion().erode(&mut after, SEED).unwrap();
+
+        let sum = |map: &NoiseMap| values(map).iter().sum::<f64>();
+        assert!(sum(&after) <= sum(&before) + 1e-9);
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        let mut map = map();
+        let invalid = HydraulicErosion { inertia: 1.5, ..Default::default() };
+        assert!(matches!(invalid.erode(&mut map, SEED), Err(WorldGenError::OutOfRange { .. })));
+
+        let invalid = HydraulicErosion { radius: 0, ..Default::default() };
+        assert!(invalid.erode(&mut map, SEED).is_err());
+
+        assert_eq!(erosion().erode(&mut NoiseMap::new(4, 4), SEED), Err(WorldGenError::EmptyMap));
+    }
+}
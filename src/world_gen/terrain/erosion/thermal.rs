@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+
+use crate::world_gen::error::WorldGenError;
+use crate::world_gen::grid::NEIGHBOURS;
+use crate::world_gen::noise::noise_map::NoiseMap;
+
+/// Thermal weathering.
+/// 
+/// Wherever the terrain is steeper than the talus angle, material crumbles off and slides downhill, 
+/// until the slope is no steeper than the angle. This leaves scree slopes at the foot of cliffs,
+/// and flattens single cell spikes.
+/// 
+/// Every iteration is computed from the terrain as it was before the iteration, 
+/// so the result doesn't depend on the order the cells are visited in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThermalErosion {
+    pub iterations: usize,
+    pub talus_angle: f64,   // The steepest slope that doesn't crumble, in degrees
+    pub height_scale: f64,  // How many cells a value of 1.0 is tall, used to measure slopes
+    pub rate: f64,          // How much of the material above the talus angle is moved every iteration, in [0; 1]
+}
+
+impl ThermalErosion {
+    /// Returns an error if any of the settings are invalid.
+    pub fn validate(&self) -> Result<(), WorldGenError> {
+        WorldGenError::check_range("talus angle", self.talus_angle, 0.0, 90.0)?;
+        WorldGenError::check_range("thermal erosion rate", self.rate, 0.0, 1.0)?;
+        WorldGenError::check_finite("height scale", self.height_scale)?;
+        if self.height_scale <= 0.0 {
+            return Err(WorldGenError::OutOfRange { 
+                parameter: "height scale", value: self.height_scale, min: 0.0, max: f64::INFINITY,
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns the largest height difference between two neighbouring cells (1 cell apart) that doesn't crumble.
+    fn talus(&self) -> f64 {
+        self.talus_angle.to_radians().tan() / self.height_scale
+    }
+
+    /// Erodes the NoiseMap in place.
+    /// 
+    /// No material is lost, it is only moved around inside the map.
+    pub fn erode(&self, map: &mut NoiseMap) -> Result<(), WorldGenError> {
+        self.validate()?;
+        let values = map.try_grid_mut()?;
+
+        let (height, width) = values.size();
+        let talus = self.talus();
+        let mut before = values.clone();
+
+        for _ in 0..self.iterations {
+            before.clone_from(values);
+
+            for row in 0..height {
+                for column in 0..width {
+                    let cell = before[(row, column)];
+
+                    // Find the neighbours that are too far below this cell
+                    let mut steep = [((0, 0), 0.0); 8];
+                    let mut count = 0;
+                    let mut total_excess = 0.0;
+                    let mut max_excess: f64 = 0.0;
+                    for (d_row, d_column, distance) in NEIGHBOURS {
+                        let (n_row, n_column) = (row as isize + d_row, column as isize + d_column);
+                        if n_row < 0 || n_column < 0 || n_row >= height as isize || n_column >= width as isize {
+                            continue;
+                        }
+                        let neighbour = (n_row as usize, n_column as usize);
+                        let excess = cell - before[neighbour] - talus * distance;
+                        if excess > 0.0 {
+                            steep[count] = (neighbour, excess);
+                            count += 1;
+                            total_excess += excess;
+                            max_excess = max_excess.max(excess);
+                        }
+                    }
+
+                    // Moving half of the steepest excess evens out the steepest slope,
+                    // the rest is shared between the steep neighbours by how steep they are
+                    let moved = self.rate * max_excess / 2.0;
+                    for &(neighbour, excess) in &steep[..count] {
+                        let share = moved * excess / total_excess;
+                        values[neighbour] += share;
+                        values[(row, column)] -= share;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for ThermalErosion {
+    fn default() -> Self {
+        ThermalErosion {
+            iterations: 50,
+            talus_angle: 45.0,
+            height_scale: 100.0,
+            rate: 0.5,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noise::Perlin;
+
+    use crate::world_gen::seed::WorldSeed;
+    use super::*;
+
+    fn map() -> NoiseMap {
+        NoiseMap::from_noisefn(40, 56, 10, 6, 2.0, 0.6, Perlin::new(), WorldSeed::from_u64(0x5EED))
+    }
+
+    fn values(map: &NoiseMap) -> Vec<f64> {
+        (0..map.get_height())
+            .flat_map(|row| (0..map.get_width()).map(move |column| (row, column)))
+            .map(|(row, column)| map.get_value(row, column))
+            .collect()
+    }
+
+    /// Returns the largest height difference between two horizontally or vertically neighbouring cells.
+    fn steepest_slope(map: &NoiseMap) -> f64 {
+        let mut steepest: f64 = 0.0;
+        for row in 0..map.get_height() {
+            for column in 0..map.get_width() {
+                let value = map.get_value(row, column);
+                if row + 1 < map.get_height() {
+                    steepest = steepest.max((value - map.get_value(row + 1, column)).abs());
+                }
+                if column + 1 < map.get_width() {
+                    steepest = steepest.max((value - map.get_value(row, column + 1)).abs());
+                }
+            }
+        }
+        steepest
+    }
+
+    #[test]
+    fn thermal_erosion_is_deterministic() {
+        let (mut first, mut second) = (map(), map());
+        ThermalErosion::default().erode(&mut first).unwrap();
+        ThermalErosion::default().erode(&mut second).unwrap();
+
+        assert_eq!(values(&first), values(&second));
+    }
+
+    #[test]
+    fn thermal_erosion_conserves_material() {
+        let before = map();
+        let mut after = map();
+        ThermalErosion::default().erode(&mut after).unwrap();
+
+        let sum = |map: &NoiseMap| values(map).iter().sum::<f64>();
+        assert!((sum(&before) - sum(&after)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn slopes_approach_talus_angle() {
+        let erosion = ThermalErosion { iterations: 500, talus_angle: 30.0, ..Default::default() };
+        let before = map();
+        let mut after = map();
+        erosion.erode(&mut after).unwrap();
+
+        assert!(steepest_slope(&before) > erosion.talus() * 2.0);
+        assert!(steepest_slope(&after) < erosion.talus() * 1.1);
+    }
+
+    #[test]
+    fn spikes_are_flattened() {
+        let mut map = map();
+        let values = map.try_grid_mut().unwrap();
+        values.iter_mut().for_each(|value| *value = 0.5);
+        values[(20, 20)] = 1.0;
+        let erosion = ThermalErosion { iterations: 500, ..Default::default() };
+        erosion.erode(&mut map).unwrap();
+
+        for (d_row, d_column, distance) in NEIGHBOURS {
+            let neighbour = map.get_value((20 + d_row) as usize, (20 + d_column) as usize);
+            assert!(map.get_value(20, 20) - neighbour <= erosion.talus() * distance + 1e-6);
+        }
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        let mut map = map();
+        for invalid in [
+            ThermalErosion { talus_angle: 95.0, ..Default::default() },
+            ThermalErosion { rate: -0.1, ..Default::default() },
+            ThermalErosion { height_scale: 0.0, ..Default::default() },
+            ThermalErosion { height_scale: f64::NAN, ..Default::default() },
+        ] {
+            assert!(invalid.erode(&mut map).is_err());
+        }
+    }
+}
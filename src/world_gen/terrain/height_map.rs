@@ -1,7 +1,76 @@
-use crate::world_gen::noise::noise_map::NoiseMap;
+use std::path::Path;
+
+use image::{ImageBuffer, Luma};
+use serde::{Deserialize, Serialize};
+
+use crate::world_gen::error::WorldGenError;
+use crate::world_gen::grid::Grid2D;
+use crate::world_gen::noise::noise_map::{read_raw, write_raw, NoiseMap};
+use super::biome::{Biome, BiomeMap};
+use super::terrain_type::{TerrainClass, TerrainRegistry, TerrainType};
 
 pub type Height = i32;
 
+/// The heights a world is built within, and where the sea surface is.
+/// 
+/// Both `min` and `max` are valid heights.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerticalRange {
+    pub min: Height,
+    pub max: Height,
+    pub sea_level: Height,  // The lowest height that isn't covered by the ocean
+}
+
+impl VerticalRange {
+    /// Returns an error if the range is empty, or the sea level is outside of it.
+    pub fn validate(&self) -> Result<(), WorldGenError> {
+        if self.min >= self.max {
+            return Err(WorldGenError::InvalidRange { parameter: "heights", min: self.min as f64, max: self.max as f64 });
+        }
+        WorldGenError::check_range("sea level", self.sea_level as f64, self.min as f64, self.max as f64)?;
+        Ok(())
+    }
+
+    /// Returns true if the height is within the range.
+    pub fn contains(&self, height: Height) -> bool {
+        (self.min..=self.max).contains(&height)
+    }
+
+    /// Maps a value in [0; 1] linearly to a height in the range. Values outside [0; 1] are clamped.
+    pub fn height_at(&self, value: f64) -> Height {
+        let span = (self.max - self.min) as f64;
+        self.min + (value.clamp(0.0, 1.0) * span).round() as Height
+    }
+
+    /// Maps an offset from sea level in [-1; 1] to a height in the range, 
+    /// where -1 is the bottom, 0 is sea level and 1 is the top. Offsets outside [-1; 1] are clamped.
+    /// 
+    /// Unlike [`height_at`](Self::height_at), the same offset is always on the same side of the sea, whatever the range.
+    pub fn height_at_offset(&self, offset: f64) -> Height {
+        let offset = offset.clamp(-1.0, 1.0);
+        let span = match offset < 0.0 {
+            true => (self.sea_level - self.min) as f64,
+            false => (self.max - self.sea_level) as f64,
+        };
+        self.sea_level + (offset * span).round() as Height
+    }
+
+    /// Maps a height in the range linearly to [0; 1], the opposite of [`height_at`](Self::height_at).
+    pub fn fraction(&self, height: Height) -> f64 {
+        (height.clamp(self.min, self.max) - self.min) as f64 / (self.max - self.min) as f64
+    }
+}
+
+impl Default for VerticalRange {
+    fn default() -> Self {
+        VerticalRange {
+            min: 0,
+            max: 100,
+            sea_level: 45,
+        }
+    }
+}
+
 /// Bandaid fix, but some form of this should propably be used.
 /// 
 /// Need to implement a better version of this, but i like the idea of having this 'higher level' version of [`NoiseMap`] 
@@ -10,68 +79,326 @@ pub type Height = i32;
 /// I also disslike the way i have been handling the ranges of [`TerrainType`](super::terrain_type::TerrainType)'s when they are floats, 
 /// so mapping them to integers also solves that.
 pub struct HeightMap {
-    height: usize,
-    width: usize,
-    values: Vec<Height>,
+    values: Grid2D<Height>,
+    overlay: Grid2D<Option<TerrainType>>,     // Terrain that isn't identified by height, like rivers
+    range: VerticalRange,
 }
 
 /// Methods for accessing data in the HeightMap.
 impl HeightMap {
-    fn index(&self, row: usize, column: usize) -> usize {
-        assert!(row < self.height);
-        assert!(column < self.width);
-    
-        row * self.width + column
-    }
-    
     /// Returns the height value at the given position
     pub fn get(&self, row: usize, column: usize) -> Height {
-        self.values[ self.index(row, column) ]
+        self.values[(row, column)]
+    }
+
+    /// Sets the height value at the given position
+    pub fn set(&mut self, row: usize, column: usize, height: Height) {
+        self.values.set(row, column, height);
+    }
+
+    /// Returns the class of terrain at the given position.
+    /// 
+    /// This is the class of the terrain set with [`set_terrain`](Self::set_terrain) if there is any,
+    /// otherwise the class is identified from the height above sea level.
+    /// 
+    /// # Errors
+    /// Heights outside the range of the HeightMap return [`WorldGenError::UnknownHeight`].
+    pub fn terrain<'r>(
+        &self, 
+        registry: &'r TerrainRegistry, 
+        row: usize, 
+        column: usize,
+    ) -> Result<&'r TerrainClass, WorldGenError> {
+        self.terrain_with(registry, row, column, None)
+    }
+
+    /// Returns the class of terrain at the given position, in the biome the BiomeMap has there.
+    /// 
+    /// Like [`terrain`](Self::terrain), but classes limited to that biome are picked over the classes found everywhere.
+    /// 
+    /// # Errors
+    /// Heights outside the range of the HeightMap return [`WorldGenError::UnknownHeight`].
+    /// 
+    /// # Panics
+    /// Panics if the position is outside the BiomeMap.
+    pub fn terrain_in<'r>(
+        &self, 
+        registry: &'r TerrainRegistry, 
+        row: usize, 
+        column: usize,
+        biome_map: &BiomeMap,
+    ) -> Result<&'r TerrainClass, WorldGenError> {
+        self.terrain_with(registry, row, column, Some(biome_map.get(row, column)))
+    }
+
+    fn terrain_with<'r>(
+        &self, 
+        registry: &'r TerrainRegistry, 
+        row: usize, 
+        column: usize,
+        biome: Option<Biome>,
+    ) -> Result<&'r TerrainClass, WorldGenError> {
+        match self.get_overlay(row, column) {
+            Some(terrain) => registry.overlay(terrain),
+            None => {
+                let height = self.get(row, column);
+                if !self.range.contains(height) {
+                    return Err(WorldGenError::UnknownHeight(height));
+                }
+                registry.ident_in(&(height - self.range.sea_level), biome)
+            }
+        }
+    }
+
+    /// Returns the terrain set with [`set_terrain`](Self::set_terrain) at the given position, if there is any.
+    pub fn get_overlay(&self, row: usize, column: usize) -> Option<TerrainType> {
+        self.overlay[(row, column)]
+    }
+
+    /// Overrides the terrain at the given position, regardless of its height.
+    pub fn set_terrain(&mut self, row: usize, column: usize, terrain: TerrainType) {
+        self.overlay.set(row, column, Some(terrain));
     }
 
     pub fn get_height(&self) -> usize {
-        self.height
+        self.values.get_height()
     }
 
     pub fn get_width(&self) -> usize {
-        self.width
+        self.values.get_width()
+    }
+
+    /// Returns the heights of the HeightMap.
+    pub fn grid(&self) -> &Grid2D<Height> {
+        &self.values
+    }
+
+    /// Returns the heights the HeightMap is built within.
+    pub fn get_range(&self) -> VerticalRange {
+        self.range
     }
 }
 
 /// Methods for creating a HeightMap.
 impl HeightMap {
 
-    /// Returns an empty HeightMap with the given size.
-    fn from_size(height: usize, width: usize) -> HeightMap {
+    /// Creates a HeightMap from the given heights, without any terrain set on top of them.
+    pub fn from_grid(values: Grid2D<Height>, range: VerticalRange) -> Self {
         HeightMap {
-            height,
-            width,
-            values: Vec::with_capacity(height * width),
+            overlay: Grid2D::new(values.get_height(), values.get_width(), None),
+            values,
+            range,
         }
     }
 
+    /// Creates a HeightMap with the given size, where the height at (row, column) is `f(row, column)`.
+    pub fn from_fn<F>(height: usize, width: usize, range: VerticalRange, f: F) -> Self
+    where
+        F: FnMut(usize, usize) -> Height
+    {
+        HeightMap::from_grid(Grid2D::from_fn(height, width, f), range)
+    }
+
     /// There is realy no reason to create a HeightMap in any other way.
     /// 
     /// ## Returns
     /// Returns a HeightMap where every value is the result of parsing every, 
     /// value from a NoiseMap through the given mapping function.
-    pub fn from_noise_map<F>(noise_map: &NoiseMap, mapper: F) -> Self 
+    /// 
+    /// The mapping function should only return heights within `range`, 
+    /// e.g. by using [`VerticalRange::height_at`].
+    pub fn from_noise_map<F>(noise_map: &NoiseMap, range: VerticalRange, mapper: F) -> Self 
     where
         F: Fn(f64) -> Height
     {   
-        let mut height_map =
-            HeightMap::from_size(noise_map.get_height(), noise_map.get_width());
-        
-        for column in 0..height_map.width {
-            for row in 0..height_map.height {
-                let noise_val = noise_map.get_value(row, column);
-                let height = mapper(noise_val);
-
-                height_map
-                    .values
-                    .push(height);
-            }
+        HeightMap::from_fn(noise_map.get_height(), noise_map.get_width(), range, |row, column| {
+            mapper(noise_map.get_value(row, column))
+        })
+    }
+
+    /// Creates a HeightMap from a grayscale image, e.g. terrain painted in an external tool.
+    /// 
+    /// Black is mapped to the bottom of `range` and white to the top, 
+    /// the same way [`save_as_png16`](Self::save_as_png16) writes them.
+    pub fn from_image(path: impl AsRef<Path>, range: VerticalRange) -> Result<Self, WorldGenError> {
+        range.validate()?;
+        let noise_map = NoiseMap::from_image(path)?;
+        Ok(HeightMap::from_noise_map(&noise_map, range, |v| range.height_at(v)))
+    }
+
+    /// Creates a HeightMap from a file written by [`save_as_raw`](Self::save_as_raw).
+    /// 
+    /// The file only contains the heights, so the size and range of the map have to be given.
+    /// 
+    /// # Errors
+    /// Returns [`WorldGenError::InvalidRange`], with the lowest and highest height in the file, 
+    /// if any of the heights are not finite or fall outside `range`.
+    pub fn from_raw(
+        path: impl AsRef<Path>, 
+        height: usize, 
+        width: usize, 
+        range: VerticalRange,
+    ) -> Result<Self, WorldGenError> {
+        range.validate()?;
+        let raw = read_raw(path, height * width)?;
+
+        // A file written for another range (or not by us at all) would break everything using the range later
+        if raw.iter().any(|v| !v.is_finite() || !range.contains(v.round() as Height)) {
+            let (lowest, highest) = raw
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(lowest, highest), &v| {
+                    (lowest.min(v as f64), highest.max(v as f64))
+                });
+            return Err(WorldGenError::InvalidRange { parameter: "raw heights", min: lowest, max: highest });
+        }
+
+        let values = raw.into_iter().map(|v| v.round() as Height).collect();
+
+        Ok(HeightMap::from_grid(Grid2D::from_vec(height, width, values)?, range))
+    }
+}
+
+/// Methods for exporting a HeightMap.
+impl HeightMap {
+    /// Saves the HeightMap as a 16bit grayscale PNG at the given path.
+    /// 
+    /// Heights are mapped linearly from the range of the HeightMap to black and white.
+    pub fn save_as_png16(&self, path: impl AsRef<Path>) -> Result<(), WorldGenError> {
+        if self.values.is_empty() {
+            return Err(WorldGenError::EmptyMap);
+        }
+
+        let buffer = self.values
+            .iter()
+            .map(|&height| (self.range.fraction(height) * u16::MAX as f64).round() as u16)
+            .collect();
+
+        let img: ImageBuffer<Luma<u16>, Vec<u16>> =
+            ImageBuffer::from_raw(self.get_width() as u32, self.get_height() as u32, buffer)
+                .expect("HeightMap size does not match its values");
+
+        img.save(path).map_err(|e| WorldGenError::Image(e.to_string()))
+    }
+
+    /// Saves the heights as raw, little-endian 32bit floats, row by row.
+    /// 
+    /// The file does not contain the size of the map, so it has to be known when reading it back.
+    pub fn save_as_raw(&self, path: impl AsRef<Path>) -> Result<(), WorldGenError> {
+        if self.values.is_empty() {
+            return Err(WorldGenError::EmptyMap);
+        }
+
+        let values: Vec<f32> = self.values.iter().map(|&height| height as f32).collect();
+        write_raw(path, &values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A HeightMap where every height is unique, so mixed up rows and columns are caught.
+    fn height_map(height: usize, width: usize) -> HeightMap {
+        let range = VerticalRange { min: -64, max: -64 + (height * width) as Height - 1, sea_level: 0 };
+        let values = Grid2D::from_vec(height, width, (range.min..=range.max).collect()).unwrap();
+        HeightMap::from_grid(values, range)
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        let map = height_map(6, 11);
+        let path = std::env::temp_dir().join("height_map_raw_round_trip.raw");
+
+        map.save_as_raw(&path).unwrap();
+        let loaded = HeightMap::from_raw(&path, 6, 11, map.get_range()).unwrap();
+        let narrower = VerticalRange { min: -10, max: 0, sea_level: 0 };
+        let out_of_range = HeightMap::from_raw(&path, 6, 11, narrower);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            out_of_range,
+            Err(WorldGenError::InvalidRange { min, max, .. }) if min == -64.0 && max == 1.0,
+        ));
+
+        assert_eq!(loaded.values, map.values);
+    }
+
+    #[test]
+    fn png16_round_trip() {
+        let map = height_map(6, 11);
+        let path = std::env::temp_dir().join("height_map_png16_round_trip.png");
+        map.save_as_png16(&path).unwrap();
+        let loaded = HeightMap::from_image(&path, map.get_range()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((loaded.get_height(), loaded.get_width()), (6, 11));
+        assert_eq!(loaded.values, map.values);
+    }
+
+    #[test]
+    fn non_square_maps_are_not_transposed() {
+        // The same size as the tall demos
+        let (height, width) = (512, 1024);
+        let cells = (height * width) as f64 - 1.0;
+        let noise = Grid2D::from_fn(height, width, |row, column| (row * width + column) as f64 / cells);
+        let noise_map = NoiseMap::from_grid(noise).unwrap();
+
+        let range = VerticalRange { min: 0, max: cells as Height, sea_level: 0 };
+        let map = HeightMap::from_noise_map(&noise_map, range, |v| range.height_at(v));
+
+        assert_eq!((map.get_height(), map.get_width()), (height, width));
+        for (row, column) in [(0, 1), (1, 0), (0, 1023), (511, 0), (300, 700), (511, 1023)] {
+            assert_eq!(map.get(row, column), (row * width + column) as Height);
         }
-        height_map
+    }
+
+    #[test]
+    fn png16_rejects_empty_range() {
+        let path = std::env::temp_dir().join("height_map_png16_empty_range.png");
+        height_map(2, 2).save_as_png16(&path).unwrap();
+
+        let empty = VerticalRange { min: 10, max: 10, sea_level: 10 };
+        let loaded = HeightMap::from_image(&path, empty);
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.is_err());
+    }
+
+    #[test]
+    fn vertical_range_validation() {
+        assert_eq!(VerticalRange::default().validate(), Ok(()));
+        assert_eq!(VerticalRange { min: -64, max: 320, sea_level: 62 }.validate(), Ok(()));
+        assert!(VerticalRange { min: -64, max: 320, sea_level: 400 }.validate().is_err());
+        assert!(VerticalRange { min: 320, max: -64, sea_level: 62 }.validate().is_err());
+    }
+
+    #[test]
+    fn heights_are_mapped_across_the_range() {
+        let range = VerticalRange { min: -64, max: 320, sea_level: 62 };
+        assert_eq!(range.height_at(0.0), -64);
+        assert_eq!(range.height_at(1.0), 320);
+        assert_eq!(range.height_at(0.5), 128);
+        assert_eq!(range.fraction(range.height_at(0.25)), 0.25);
+
+        assert_eq!(range.height_at_offset(-1.0), -64);
+        assert_eq!(range.height_at_offset(0.0), 62);
+        assert_eq!(range.height_at_offset(0.5), 191);
+        assert_eq!(range.height_at_offset(2.0), 320);
+    }
+
+    #[test]
+    fn terrain_is_relative_to_sea_level() {
+        let registry = TerrainRegistry::default();
+        let mut map = height_map(2, 2);
+        map.range = VerticalRange { min: -64, max: 320, sea_level: 62 };
+        map.values = Grid2D::from_vec(2, 2, vec![-64, 61, 62, 320]).unwrap();
+
+        let kinds: Vec<_> = [(0, 0), (0, 1), (1, 0), (1, 1)]
+            .iter()
+            .map(|&(row, column)| map.terrain(&registry, row, column).unwrap().kind)
+            .collect();
+        assert_eq!(kinds, [TerrainType::DeepOcean, TerrainType::Ocean, TerrainType::Beach, TerrainType::HighLand]);
+
+        map.set(1, 1, 321);
+        assert_eq!(map.terrain(&registry, 1, 1), Err(WorldGenError::UnknownHeight(321)));
     }
 }
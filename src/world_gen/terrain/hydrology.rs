@@ -0,0 +1,538 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::world_gen::grid::{Grid2D, NEIGHBOURS};
+use super::height_map::{Height, HeightMap};
+use super::terrain_type::TerrainType;
+
+/// Where the water on every cell of a [`HeightMap`] flows, and how much water flows through every cell.
+///
+/// Water flows to the lowest of the 8 neighbours of a cell (D8).
+/// Water trapped in a pit is led out over the lowest point of its rim,
+/// so all water ends up in the ocean or flows off the edge of the map.
+pub struct FlowMap {
+    downstream: Grid2D<Option<usize>>,  // The index of the cell the water flows to, None for the ocean and the edge of the map
+    accumulation: Grid2D<u32>,          // The number of cells draining through the cell, itself included
+}
+
+impl FlowMap {
+    /// Computes the flow of water over the HeightMap. Cells below its sea level are ocean.
+    pub fn new(height_map: &HeightMap) -> Self {
+        let (height, width) = (height_map.get_height(), height_map.get_width());
+        let sea_level = height_map.get_range().sea_level;
+        let flood = priority_flood(height_map);
+
+        let mut downstream = vec![None; height * width];
+        for (i, cell) in downstream.iter_mut().enumerate() {
+            let (row, column) = (i / width, i % width);
+            if height_map.get(row, column) < sea_level {
+                continue;
+            }
+
+            // Follow the steepest way down,
+            // and where the ground is flat (or was a pit) follow the way the flood came from
+            let mut steepest = 0.0;
+            for neighbour in neighbours(row, column, height, width) {
+                let (n, distance) = neighbour;
+                let slope = (flood.filled[i] - flood.filled[n]) as f64 / distance;
+                if slope > steepest {
+                    steepest = slope;
+                    *cell = Some(n);
+                }
+            }
+            if cell.is_none() {
+                *cell = flood.parent[i];
+            }
+        }
+
+        // Every cell drains into a cell that was flooded before it,
+        // so going through them backwards passes the water all the way down
+        let mut accumulation = vec![1; height * width];
+        for &i in flood.order.iter().rev() {
+            if let Some(d) = downstream[i] {
+                accumulation[d] += accumulation[i];
+            }
+        }
+
+        FlowMap {
+            downstream: Grid2D::from_vec(height, width, downstream).expect("every cell has a downstream"),
+            accumulation: Grid2D::from_vec(height, width, accumulation).expect("every cell has an accumulation"),
+        }
+    }
+
+    /// Returns the number of cells draining through the given position, itself included.
+    pub fn get_accumulation(&self, row: usize, column: usize) -> u32 {
+        self.accumulation[(row, column)]
+    }
+
+    /// Returns the position the water at the given position flows to,
+    /// or None if it is in the ocean or flows off the edge of the map.
+    pub fn get_downstream(&self, row: usize, column: usize) -> Option<(usize, usize)> {
+        let width = self.get_width();
+        self.downstream[(row, column)].map(|i| (i / width, i % width))
+    }
+
+    /// Returns the number of cells draining through every cell.
+    pub fn accumulation(&self) -> &Grid2D<u32> {
+        &self.accumulation
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.accumulation.get_height()
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.accumulation.get_width()
+    }
+}
+
+/// Settings for the rivers carved by [`HeightMap::carve_rivers`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RiverSettings {
+    pub threshold: u32,     // The number of cells that have to drain through a cell for it to become a river
+    pub depth: Height,      // How far rivers are carved into the ground
+}
+
+impl Default for RiverSettings {
+    fn default() -> Self {
+        RiverSettings {
+            threshold: 500,
+            depth: 1,
+        }
+    }
+}
+
+/// Methods for adding water to HeightMaps.
+impl HeightMap {
+    /// Carves rivers into the HeightMap wherever enough water flows,
+    /// and marks them as [`TerrainType::River`].
+    ///
+    /// As water only flows downhill (or out of a pit), every river runs until it reaches the ocean, a lake
+    /// or the edge of the map. Rivers are never carved below sea level, or into lakes.
+    /// 
+    /// Lakes should be added with [`fill_lakes`](Self::fill_lakes) before the rivers,
+    /// so rivers flowing out of them start from the lowest point of their shore.
+    ///
+    /// Returns the flow of water used to place the rivers.
+    pub fn carve_rivers(&mut self, settings: &RiverSettings) -> FlowMap {
+        let flow = FlowMap::new(self);
+        let sea_level = self.get_range().sea_level;
+
+        for row in 0..self.get_height() {
+            for column in 0..self.get_width() {
+                let height = self.get(row, column);
+                if height < sea_level || flow.get_accumulation(row, column) < settings.threshold {
+                    continue;
+                }
+                if self.get_overlay(row, column) == Some(TerrainType::Lake) {
+                    continue;
+                }
+
+                self.set(row, column, (height - settings.depth).max(sea_level));
+                self.set_terrain(row, column, TerrainType::River);
+            }
+        }
+        flow
+    }
+
+    /// Raises every pit in the HeightMap up to the height where water would spill out of it, 
+    /// so water can flow from every cell to the ocean or the edge of the map.
+    pub fn fill_depressions(&mut self) {
+        let flood = priority_flood(self);
+
+        for (i, &height) in flood.filled.iter().enumerate() {
+            self.set(i / self.get_width(), i % self.get_width(), height);
+        }
+    }
+
+    /// Fills the pits in the HeightMap with water, up to the height where it would spill out of them,
+    /// and marks them as [`TerrainType::Lake`].
+    /// 
+    /// Pits smaller than the minimum size of a lake are left as they are.
+    /// 
+    /// Returns the lakes that were added.
+    pub fn fill_lakes(&mut self, settings: &LakeSettings) -> LakeMap {
+        let lakes = LakeMap::new(self, settings);
+
+        for lake in lakes.get_lakes() {
+            for &(row, column) in &lake.cells {
+                self.set(row, column, lake.spill_height);
+                self.set_terrain(row, column, TerrainType::Lake);
+            }
+        }
+        lakes
+    }
+}
+
+/// Settings for the lakes added by [`HeightMap::fill_lakes`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LakeSettings {
+    pub min_size: usize,    // The number of cells a pit has to cover to become a lake
+}
+
+impl Default for LakeSettings {
+    fn default() -> Self {
+        LakeSettings {
+            min_size: 20,
+        }
+    }
+}
+
+/// A single lake.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lake {
+    pub spill_height: Height,           // The height of the water, where it starts spilling out of the lake
+    pub cells: Vec<(usize, usize)>,     // The (row, column) of every cell covered by the lake
+}
+
+/// The lakes found in the pits of a [`HeightMap`].
+/// 
+/// Works as a mask over the HeightMap, telling which lake (if any) covers every cell.
+pub struct LakeMap {
+    labels: Grid2D<Option<usize>>,  // The index of the lake covering every cell
+    lakes: Vec<Lake>,
+}
+
+impl LakeMap {
+    /// Finds every pit in the HeightMap covering at least `settings.min_size` cells. Cells below its sea level are ocean.
+    /// 
+    /// Pits next to each other, but spilling over at different heights, are different lakes.
+    pub fn new(height_map: &HeightMap, settings: &LakeSettings) -> Self {
+        let (height, width) = (height_map.get_height(), height_map.get_width());
+        let flood = priority_flood(height_map);
+        let is_pit = |i: usize| flood.filled[i] > height_map.get(i / width, i % width);
+
+        let mut labels = vec![None; height * width];
+        let mut lakes = Vec::new();
+        let mut visited = vec![false; height * width];
+
+        for start in 0..height * width {
+            if visited[start] || !is_pit(start) {
+                continue;
+            }
+
+            // Gather all the water connected to this cell, at the same height
+            let spill_height = flood.filled[start];
+            let mut cells = Vec::new();
+            let mut queue = VecDeque::from([start]);
+            visited[start] = true;
+
+            while let Some(i) = queue.pop_front() {
+                cells.push(i);
+                for (n, _) in neighbours(i / width, i % width, height, width) {
+                    if !visited[n] && is_pit(n) && flood.filled[n] == spill_height {
+                        visited[n] = true;
+                        queue.push_back(n);
+                    }
+                }
+            }
+
+            if cells.len() < settings.min_size {
+                continue;
+            }
+            for &i in &cells {
+                labels[i] = Some(lakes.len());
+            }
+            lakes.push(Lake {
+                spill_height,
+                cells: cells.into_iter().map(|i| (i / width, i % width)).collect(),
+            });
+        }
+
+        LakeMap {
+            labels: Grid2D::from_vec(height, width, labels).expect("every cell has a label"),
+            lakes,
+        }
+    }
+
+    /// Returns the index of the lake covering the given position, if there is one.
+    pub fn get_lake(&self, row: usize, column: usize) -> Option<usize> {
+        self.labels[(row, column)]
+    }
+
+    /// Returns true if the given position is covered by a lake.
+    pub fn is_lake(&self, row: usize, column: usize) -> bool {
+        self.get_lake(row, column).is_some()
+    }
+
+    /// Returns all the lakes, ordered by their first cell (row by row).
+    pub fn get_lakes(&self) -> &[Lake] {
+        &self.lakes
+    }
+}
+
+/// The result of flooding a HeightMap from the ocean and the edge of the map.
+struct Flood {
+    filled: Vec<Height>,            // The heights with every pit filled up to the point it spills over
+    order: Vec<usize>,              // The cells in the order they were flooded, which is from low to high
+    parent: Vec<Option<usize>>,     // The cell every cell was flooded from
+}
+
+/// Returns the cells next to the given position, and their distance to it.
+fn neighbours(row: usize, column: usize, height: usize, width: usize) -> impl Iterator<Item = (usize, f64)> {
+    NEIGHBOURS
+        .into_iter()
+        .filter_map(move |(d_row, d_column, distance)| {
+            let (n_row, n_column) = (row as isize + d_row, column as isize + d_column);
+            match n_row >= 0 && n_column >= 0 && n_row < height as isize && n_column < width as isize {
+                true => Some((n_row as usize * width + n_column as usize, distance)),
+                false => None,
+            }
+        })
+}
+
+/// Priority-flood (Barnes et al. 2014).
+///
+/// Water rises from the ocean (everything below the sea level of the HeightMap) and the edge of the map, always flooding the lowest cell next to it first.
+/// Cells lower than the water that reaches them are part of a pit, and are filled up to the water.
+///
+/// Ties are broken by the order cells are reached in, so the result is always the same.
+fn priority_flood(height_map: &HeightMap) -> Flood {
+    let (height, width) = (height_map.get_height(), height_map.get_width());
+    let sea_level = height_map.get_range().sea_level;
+
+    let mut filled = height_map.grid().as_slice().to_vec();
+
+    let mut flooded = vec![false; height * width];
+    let mut parent = vec![None; height * width];
+    let mut order = Vec::with_capacity(height * width);
+    let mut queue = BinaryHeap::new();
+    let mut reached = 0_usize;
+
+    for (i, &cell) in filled.iter().enumerate() {
+        let (row, column) = (i / width, i % width);
+        let on_edge = row == 0 || column == 0 || row == height - 1 || column == width - 1;
+
+        if on_edge || cell < sea_level {
+            flooded[i] = true;
+            queue.push(Reverse((cell, reached, i)));
+            reached += 1;
+        }
+    }
+
+    while let Some(Reverse((water, _, i))) = queue.pop() {
+        order.push(i);
+
+        for (n, _) in neighbours(i / width, i % width, height, width) {
+            if flooded[n] {
+                continue;
+            }
+            flooded[n] = true;
+            filled[n] = filled[n].max(water);
+            parent[n] = Some(i);
+            queue.push(Reverse((filled[n], reached, n)));
+            reached += 1;
+        }
+    }
+
+    Flood {
+        filled,
+        order,
+        parent,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noise::Perlin;
+
+    use crate::world_gen::noise::noise_map::NoiseMap;
+    use crate::world_gen::seed::WorldSeed;
+    use crate::world_gen::terrain::height_map::VerticalRange;
+    use super::*;
+
+    const RANGE: VerticalRange = VerticalRange { min: 0, max: 100, sea_level: 45 };
+    const SEA_LEVEL: Height = RANGE.sea_level;
+
+    fn height_map() -> HeightMap {
+        let n_map = NoiseMap::from_noisefn(96, 96, 40, 4, 2.0, 0.5, Perlin::new(), WorldSeed::from_u64(0x5EED));
+        HeightMap::from_noise_map(&n_map, RANGE, |v| RANGE.height_at(v))
+    }
+
+    /// A 5x5 bowl with a pit in the middle, sloping down to the ocean on the left.
+    fn bowl() -> HeightMap {
+        let n_map = NoiseMap::from_noisefn(5, 5, 10, 1, 1.0, 1.0, Perlin::new(), WorldSeed::from_u64(0));
+        let mut bowl = HeightMap::from_noise_map(&n_map, RANGE, |_| 0);
+        let rows = [
+            [40, 60, 60, 60, 60],
+            [40, 55, 58, 58, 60],
+            [40, 50, 48, 58, 60],
+            [40, 55, 58, 58, 60],
+            [40, 60, 60, 60, 60],
+        ];
+        for (row, heights) in rows.iter().enumerate() {
+            for (column, &height) in heights.iter().enumerate() {
+                bowl.set(row, column, height);
+            }
+        }
+        bowl
+    }
+
+    /// Returns the number of land cells (not on the edge) that are lower than all their neighbours.
+    fn count_pits(height_map: &HeightMap) -> usize {
+        let (height, width) = (height_map.get_height(), height_map.get_width());
+        let mut pits = 0;
+        for row in 1..height - 1 {
+            for column in 1..width - 1 {
+                let cell = height_map.get(row, column);
+                let lowest = neighbours(row, column, height, width)
+                    .map(|(n, _)| height_map.get(n / width, n % width))
+                    .min()
+                    .unwrap();
+                if cell >= SEA_LEVEL && cell < lowest {
+                    pits += 1;
+                }
+            }
+        }
+        pits
+    }
+
+    /// Follows the water from the given position, and returns the position it stops at.
+    fn follow(flow: &FlowMap, mut position: (usize, usize)) -> (usize, usize) {
+        for _ in 0..flow.get_height() * flow.get_width() {
+            match flow.get_downstream(position.0, position.1) {
+                Some(next) => position = next,
+                None => return position,
+            }
+        }
+        panic!("water flows in a loop");
+    }
+
+    #[test]
+    fn all_water_reaches_ocean_or_edge() {
+        let height_map = height_map();
+        let flow = FlowMap::new(&height_map);
+        let (height, width) = (height_map.get_height(), height_map.get_width());
+
+        for row in 0..height {
+            for column in 0..width {
+                let (end_row, end_column) = follow(&flow, (row, column));
+                let on_edge = end_row == 0 || end_column == 0 || end_row == height - 1 || end_column == width - 1;
+                assert!(on_edge || height_map.get(end_row, end_column) < SEA_LEVEL);
+            }
+        }
+    }
+
+    #[test]
+    fn pits_drain_over_their_rim() {
+        let flow = FlowMap::new(&bowl());
+
+        // The pit drains left, through the lowest gap in its rim
+        assert_eq!(follow(&flow, (2, 3)), (2, 0));
+        assert_eq!(flow.get_downstream(2, 2), Some((2, 1)));
+        assert!(flow.get_accumulation(2, 1) > flow.get_accumulation(2, 2));
+    }
+
+    #[test]
+    fn filled_depressions_have_no_pits() {
+        let mut height_map = height_map();
+        assert!(count_pits(&height_map) > 0);
+
+        height_map.fill_depressions();
+        assert_eq!(count_pits(&height_map), 0);
+    }
+
+    #[test]
+    fn pits_become_lakes_at_spill_height() {
+        let mut bowl = bowl();
+        let lakes = bowl.fill_lakes(&LakeSettings { min_size: 1 });
+
+        assert_eq!(lakes.get_lakes(), &[Lake { spill_height: 50, cells: vec![(2, 2)] }]);
+        assert_eq!(lakes.get_lake(2, 2), Some(0));
+        assert!(!lakes.is_lake(2, 1));
+        assert_eq!(bowl.get(2, 2), 50);
+        assert_eq!(bowl.get_overlay(2, 2), Some(TerrainType::Lake));
+
+        // Too small to be a lake
+        let mut bowl = self::bowl();
+        assert!(bowl.fill_lakes(&LakeSettings { min_size: 2 }).get_lakes().is_empty());
+        assert_eq!(bowl.get(2, 2), 48);
+    }
+
+    #[test]
+    fn lakes_cover_every_filled_cell() {
+        let height_map = height_map();
+        let lakes = LakeMap::new(&height_map, &LakeSettings { min_size: 1 });
+        let mut filled = self::height_map();
+        filled.fill_depressions();
+
+        for row in 0..height_map.get_height() {
+            for column in 0..height_map.get_width() {
+                let raised = filled.get(row, column) > height_map.get(row, column);
+                assert_eq!(lakes.is_lake(row, column), raised);
+
+                if let Some(lake) = lakes.get_lake(row, column) {
+                    assert_eq!(lakes.get_lakes()[lake].spill_height, filled.get(row, column));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rivers_stop_at_lakes() {
+        let mut height_map = height_map();
+        let lakes = height_map.fill_lakes(&LakeSettings { min_size: 1 });
+        let lake_cells: usize = lakes.get_lakes().iter().map(|lake| lake.cells.len()).sum();
+        height_map.carve_rivers(&RiverSettings { threshold: 20, ..Default::default() });
+
+        let count = |terrain: TerrainType| (0..height_map.get_height())
+            .flat_map(|row| (0..height_map.get_width()).map(move |column| (row, column)))
+            .filter(|&(row, column)| height_map.get_overlay(row, column) == Some(terrain))
+            .count();
+        assert!(lake_cells > 0);
+        assert_eq!(count(TerrainType::Lake), lake_cells);
+        assert!(count(TerrainType::River) > 0);
+    }
+
+    #[test]
+    fn accumulation_counts_every_cell_once() {
+        let height_map = height_map();
+        let flow = FlowMap::new(&height_map);
+
+        // Every cell ends up in exactly one cell without anywhere to go
+        let mut total = 0;
+        for row in 0..flow.get_height() {
+            for column in 0..flow.get_width() {
+                if flow.get_downstream(row, column).is_none() {
+                    total += flow.get_accumulation(row, column);
+                }
+            }
+        }
+        assert_eq!(total as usize, flow.get_height() * flow.get_width());
+    }
+
+    #[test]
+    fn rivers_flow_into_more_river_or_ocean() {
+        let mut height_map = height_map();
+        let settings = RiverSettings { threshold: 50, ..Default::default() };
+        let flow = height_map.carve_rivers(&settings);
+
+        let mut rivers = 0;
+        for row in 0..height_map.get_height() {
+            for column in 0..height_map.get_width() {
+                if height_map.get_overlay(row, column) != Some(TerrainType::River) {
+                    continue;
+                }
+                rivers += 1;
+
+                assert!(height_map.get(row, column) >= SEA_LEVEL);
+                if let Some((d_row, d_column)) = flow.get_downstream(row, column) {
+                    let is_water = height_map.get_overlay(d_row, d_column).is_some();
+                    assert!(is_water || height_map.get(d_row, d_column) < SEA_LEVEL);
+                }
+            }
+        }
+        assert!(rivers > 0);
+    }
+
+    #[test]
+    fn flow_is_deterministic() {
+        let (first, second) = (FlowMap::new(&height_map()), FlowMap::new(&height_map()));
+        assert_eq!(first.downstream, second.downstream);
+        assert_eq!(first.accumulation, second.accumulation);
+    }
+}
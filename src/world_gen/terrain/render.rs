@@ -0,0 +1,278 @@
+//! A 2D renderer for previewing HeightMaps.
+//!
+//! Instead of painting every cell with the flat colour of its terrain class (see [`texture`](super::texture)),
+//! colours fade within each class, slopes are lit by the sun, and deeper water gets darker,
+//! so the shape of the terrain can be seen at a glance.
+
+use image::{ImageBuffer, Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+
+use crate::world_gen::error::WorldGenError;
+use super::biome::BiomeMap;
+use super::height_map::HeightMap;
+use super::texture::check_size;
+use super::terrain_type::{TerrainClass, TerrainRegistry};
+
+/// The height in pixels of the legend strip added below the map.
+pub const LEGEND_HEIGHT: u32 = 16;
+
+/// Settings for [`render`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    pub gradients: bool,    // Fade between the colours of every terrain class, instead of using a single colour
+    pub sun_azimuth: f64,   // The direction the sun shines from, in degrees clockwise from north
+    pub sun_altitude: f64,  // How high the sun is, in degrees above the horizon
+    pub shading: f64,       // How strong the hillshading is, in [0; 1] where 0 turns it off
+    pub exaggeration: f64,  // Heights are multiplied by this when shading, so gentle slopes show up
+    pub water_depth: f64,   // How much darker the deepest water is, in [0; 1] where 0 turns it off
+    pub legend: bool,       // Add a strip below the map, with the colours of every terrain class in order
+}
+
+impl RenderSettings {
+    /// Returns an error if any of the settings are invalid.
+    pub fn validate(&self) -> Result<(), WorldGenError> {
+        WorldGenError::check_finite("sun azimuth", self.sun_azimuth)?;
+        WorldGenError::check_range("sun altitude", self.sun_altitude, 0.0, 90.0)?;
+        WorldGenError::check_range("shading", self.shading, 0.0, 1.0)?;
+        WorldGenError::check_finite("exaggeration", self.exaggeration)?;
+        WorldGenError::check_range("water depth", self.water_depth, 0.0, 1.0)?;
+        Ok(())
+    }
+
+    /// Returns the direction of the sun, as a unit vector [east, north, up].
+    fn sun(&self) -> [f64; 3] {
+        let (azimuth, altitude) = (self.sun_azimuth.to_radians(), self.sun_altitude.to_radians());
+        [
+            azimuth.sin() * altitude.cos(),
+            azimuth.cos() * altitude.cos(),
+            altitude.sin(),
+        ]
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        // The sun in the north west is the convention for maps, otherwise valleys look like ridges
+        RenderSettings {
+            gradients: true,
+            sun_azimuth: 315.0,
+            sun_altitude: 45.0,
+            shading: 0.7,
+            exaggeration: 4.0,
+            water_depth: 0.5,
+            legend: true,
+        }
+    }
+}
+
+/// Renders an image of the HeightMap, one pixel per cell.
+///
+/// Land is shaded by how much it faces the sun, compared to flat ground, which keeps the colour of its class.
+/// Water (below sea level, rivers and lakes) is flat, but the ocean gets darker the deeper it is.
+///
+/// # Errors
+/// Returns an error if the settings are invalid, or the terrain of a cell can't be identified.
+pub fn render(
+    height_map: &HeightMap,
+    registry: &TerrainRegistry,
+    settings: &RenderSettings,
+) -> Result<RgbImage, WorldGenError> {
+    render_with(height_map, registry, None, settings)
+}
+
+/// Renders an image of the HeightMap, with the terrain of every cell identified in its biome.
+///
+/// Like [`render`], but classes limited to a biome show up where the BiomeMap has that biome.
+///
+/// # Errors
+/// Returns an error if the settings are invalid, the BiomeMap isn't the same size as the HeightMap,
+/// or the terrain of a cell can't be identified.
+pub fn render_in(
+    height_map: &HeightMap,
+    registry: &TerrainRegistry,
+    biome_map: &BiomeMap,
+    settings: &RenderSettings,
+) -> Result<RgbImage, WorldGenError> {
+    render_with(height_map, registry, Some(biome_map), settings)
+}
+
+fn render_with(
+    height_map: &HeightMap,
+    registry: &TerrainRegistry,
+    biome_map: Option<&BiomeMap>,
+    settings: &RenderSettings,
+) -> Result<RgbImage, WorldGenError> {
+    settings.validate()?;
+    if let Some(biome_map) = biome_map {
+        check_size(height_map, biome_map)?;
+    }
+
+    let range = height_map.get_range();
+    let normals = height_map.normals(settings.exaggeration);
+    let sun = settings.sun();
+    let flat = sun[2];  // How much light flat ground gets
+
+    let (height, width) = (height_map.get_height(), height_map.get_width());
+    let legend = if settings.legend { LEGEND_HEIGHT } else { 0 };
+    let mut img: RgbImage = ImageBuffer::new(width as u32, height as u32 + legend);
+
+    for row in 0..height {
+        for column in 0..width {
+            let class = match biome_map {
+                Some(biome_map) => height_map.terrain_in(registry, row, column, biome_map)?,
+                None => height_map.terrain(registry, row, column)?,
+            };
+            let cell = height_map.get(row, column);
+
+            let mut colour = match settings.gradients && !class.kind.is_overlay() {
+                true => {
+                    let (bottom, top) = bounds(class, height_map);
+                    class.colour_at((cell - bottom) as f64 / (top - bottom).max(1) as f64)
+                }
+                false => class.colour(),
+            };
+
+            let is_water = class.kind.is_overlay() || cell < range.sea_level;
+            if !is_water {
+                let normal = normals[(row, column)];
+                let light = (0..3).map(|i| normal[i] * sun[i]).sum::<f64>().max(0.0);
+                colour = brighten(colour, 1.0 + settings.shading * (light - flat));
+            } else if cell < range.sea_level {
+                let depth = (range.sea_level - cell) as f64 / (range.sea_level - range.min).max(1) as f64;
+                colour = brighten(colour, 1.0 - settings.water_depth * depth);
+            }
+
+            img.put_pixel(column as u32, row as u32, colour);
+        }
+    }
+
+    if settings.legend {
+        draw_legend(&mut img, height as u32, registry);
+    }
+    Ok(img)
+}
+
+/// Returns the lowest and highest height in the HeightMap covered by the class.
+fn bounds(class: &TerrainClass, height_map: &HeightMap) -> (i32, i32) {
+    let range = height_map.get_range();
+    let bottom = class.min.map_or(range.min, |min| range.sea_level + min);
+    let top = class.max.map_or(range.max, |max| range.sea_level + max - 1);
+    (bottom.max(range.min), top.min(range.max))
+}
+
+/// Multiplies every channel of the colour by `factor`.
+fn brighten(colour: Rgb<u8>, factor: f64) -> Rgb<u8> {
+    Rgb(colour.0.map(|channel| (channel as f64 * factor).round().clamp(0.0, 255.0) as u8))
+}
+
+/// Draws the classes of the registry, in order, as a strip from the row `top` to the bottom of the image.
+///
+/// Every class gets an equal part of the strip, fading from the bottom to the top of the class.
+fn draw_legend(img: &mut RgbImage, top: u32, registry: &TerrainRegistry) {
+    let classes = &registry.classes;
+    if classes.is_empty() {
+        return;
+    }
+
+    let width = img.width();
+    for x in 0..width {
+        let position = x as f64 / width as f64 * classes.len() as f64;
+        let class = &classes[(position as usize).min(classes.len() - 1)];
+
+        // A dark line between classes
+        let is_edge = x > 0 && (position as usize) != (((x - 1) as f64 / width as f64 * classes.len() as f64) as usize);
+        let colour = match is_edge {
+            true => Rgb([0, 0, 0]),
+            false => class.colour_at(position.fract()),
+        };
+        for y in top..img.height() {
+            img.put_pixel(x, y, colour);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_gen::terrain::height_map::{Height, VerticalRange};
+    use crate::world_gen::terrain::terrain_type::TerrainType;
+
+    const RANGE: VerticalRange = VerticalRange { min: 0, max: 100, sea_level: 45 };
+
+    fn plain() -> RenderSettings {
+        RenderSettings { gradients: false, shading: 0.0, water_depth: 0.0, legend: false, ..Default::default() }
+    }
+
+    #[test]
+    fn plain_rendering_matches_class_colours() {
+        let registry = TerrainRegistry::default();
+        let height_map = HeightMap::from_fn(3, 30, RANGE, |row, column| (row * 30 + column) as Height);
+        let img = render(&height_map, &registry, &plain()).unwrap();
+
+        assert_eq!(img.dimensions(), (30, 3));
+        for (x, y, pixel) in img.enumerate_pixels() {
+            let class = height_map.terrain(&registry, y as usize, x as usize).unwrap();
+            assert_eq!(*pixel, class.colour());
+        }
+    }
+
+    #[test]
+    fn slopes_facing_the_sun_are_brighter() {
+        let registry = TerrainRegistry::default();
+        // A ridge running north to south, high above the sea
+        let ridge = HeightMap::from_fn(5, 21, RANGE, |_, column| 90 - (column as Height - 10).abs());
+        let settings = RenderSettings { gradients: false, legend: false, ..Default::default() };
+        let img = render(&ridge, &registry, &settings).unwrap();
+
+        // The sun is in the west, so the west side is lit
+        let (west, east) = (img.get_pixel(5, 2), img.get_pixel(15, 2));
+        assert!(west[1] > east[1]);
+
+        let unshaded = render(&ridge, &registry, &plain()).unwrap();
+        assert!(unshaded.get_pixel(5, 2) == unshaded.get_pixel(15, 2));
+    }
+
+    #[test]
+    fn deeper_water_is_darker() {
+        let registry = TerrainRegistry::default();
+        let ocean = HeightMap::from_fn(1, 3, RANGE, |_, column| [10, 30, 44][column]);
+        let settings = RenderSettings { water_depth: 0.5, ..plain() };
+        let img = render(&ocean, &registry, &settings).unwrap();
+
+        assert!(img.get_pixel(0, 0)[2] < img.get_pixel(1, 0)[2]);
+        assert!(img.get_pixel(1, 0)[2] < img.get_pixel(2, 0)[2]);
+    }
+
+    #[test]
+    fn gradients_fade_within_classes() {
+        let registry = TerrainRegistry::default();
+        let high_land = registry.classes.iter().find(|class| class.kind == TerrainType::HighLand).unwrap();
+        let height_map = HeightMap::from_fn(1, 2, RANGE, |_, column| [55, 100][column]);
+        let img = render(&height_map, &registry, &RenderSettings { gradients: true, ..plain() }).unwrap();
+
+        assert_eq!(*img.get_pixel(0, 0), high_land.colour());
+        assert_eq!(img.get_pixel(1, 0).0, high_land.top_colour.unwrap());
+    }
+
+    #[test]
+    fn legend_is_added_below_the_map() {
+        let registry = TerrainRegistry::default();
+        let height_map = HeightMap::from_fn(10, 70, RANGE, |_, _| 60);
+        let img = render(&height_map, &registry, &RenderSettings { legend: true, ..plain() }).unwrap();
+
+        assert_eq!(img.dimensions(), (70, 10 + LEGEND_HEIGHT));
+        assert_eq!(*img.get_pixel(0, 10), registry.classes[0].colour());
+        assert_eq!(*img.get_pixel(69, 10 + LEGEND_HEIGHT - 1), registry.classes[6].colour());
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        let height_map = HeightMap::from_fn(1, 1, RANGE, |_, _| 50);
+        let settings = RenderSettings { sun_altitude: 120.0, ..Default::default() };
+        assert!(matches!(
+            render(&height_map, &TerrainRegistry::default(), &settings),
+            Err(WorldGenError::OutOfRange { .. })
+        ));
+    }
+}
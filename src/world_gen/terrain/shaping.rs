@@ -0,0 +1,216 @@
+//! Shapes the terrain from several layers of noise, instead of using a single NoiseMap as the height.
+//!
+//! Loosely based on the way Minecraft does it:
+//! - continentalness decides where the oceans, coasts and inland are
+//! - erosion decides how flat the land is
+//! - peaks and valleys adds the mountains and valleys, where the erosion lets it
+//!
+//! Every layer goes through its own [`Spline`], so flat plains, sharp coastlines and dramatic mountains can live in the same world.
+
+use serde::{Deserialize, Serialize};
+
+use crate::world_gen::error::WorldGenError;
+use crate::world_gen::noise::noise_map::{NoiseMap, Normalization, Region};
+use crate::world_gen::noise::noise_settings::NoiseSettings;
+use crate::world_gen::seed::WorldSeed;
+use super::height_map::{HeightMap, VerticalRange};
+use super::spline::Spline;
+
+/// Everything needed to shape the terrain of a region, except for the world seed and its [`VerticalRange`].
+///
+/// Every layer of noise gets its own seed, derived from the world seed.
+///
+/// The curves map noise values in [0; 1] to offsets from sea level, where -1 is the bottom of the world and 1 the top
+/// (see [`VerticalRange::height_at_offset`]), so the same settings work for any range.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShapingSettings {
+    pub continentalness: NoiseSettings,
+    pub erosion: NoiseSettings,
+    pub peaks: NoiseSettings,
+    pub continentalness_curve: Spline,  // From continentalness to the offset of the ground
+    pub erosion_curve: Spline,          // From erosion to how much of the peaks and valleys is kept, in [0; 1]
+    pub peaks_curve: Spline,            // From peaks and valleys to an offset added to the ground
+}
+
+impl ShapingSettings {
+    /// Returns an error if any of the settings are invalid.
+    pub fn validate(&self) -> Result<(), WorldGenError> {
+        self.continentalness.validate()?;
+        self.erosion.validate()?;
+        self.peaks.validate()?;
+
+        let curves = [
+            (&self.continentalness_curve, "continentalness curve"),
+            (&self.erosion_curve, "erosion curve"),
+            (&self.peaks_curve, "peaks curve"),
+        ];
+        for (curve, name) in curves {
+            curve.validate().map_err(|_| WorldGenError::InvalidSpline(name))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the offset of the ground from sea level, in [-1; 1], given the value of every layer of noise.
+    pub fn shape(&self, continentalness: f64, erosion: f64, peaks: f64) -> f64 {
+        let ground = self.continentalness_curve.sample(continentalness);
+        let roughness = self.erosion_curve.sample(erosion);
+
+        (ground + roughness * self.peaks_curve.sample(peaks)).clamp(-1.0, 1.0)
+    }
+}
+
+impl Default for ShapingSettings {
+    fn default() -> Self {
+        // Like the climate, the extremes should be just as common as the middle
+        let layer = NoiseSettings {
+            octaves: 4,
+            lacunarity: 2.0,
+            persistance: 0.5,
+            normalization: Normalization::Fixed { min: -0.7, max: 0.7 },
+            ..Default::default()
+        };
+        let curve = |points: &[(f64, f64)]| Spline::cubic(points.to_vec())
+            .expect("the default curves are valid");
+
+        ShapingSettings {
+            continentalness: NoiseSettings { scale: 500, ..layer.clone() },
+            erosion: NoiseSettings { scale: 300, octaves: 3, ..layer.clone() },
+            peaks: NoiseSettings { scale: 120, octaves: 5, ..layer },
+
+            // Deep oceans, a steep drop at the coast, and land slowly rising inland
+            continentalness_curve: curve(&[
+                (0.0, -0.8), (0.25, -0.5), (0.36, -0.15), (0.42, 0.02), (0.55, 0.1), (0.8, 0.25), (1.0, 0.35),
+            ]),
+            // Little erosion means rough land, a lot of it means flat plains
+            erosion_curve: curve(&[(0.0, 1.0), (0.35, 0.7), (0.6, 0.15), (1.0, 0.0)]),
+            // Mostly gentle hills and valleys, with sharp peaks at the top
+            peaks_curve: curve(&[(0.0, -0.1), (0.4, 0.0), (0.6, 0.08), (0.8, 0.35), (1.0, 0.7)]),
+        }
+    }
+}
+
+/// Methods for creating shaped HeightMaps.
+impl HeightMap {
+    /// Creates a HeightMap from the given layers of noise, shaped by the curves of the settings.
+    ///
+    /// # Errors
+    /// Returns an error if the settings are invalid, or the maps are not the same size.
+    pub fn from_shaped_noise(
+        continentalness: &NoiseMap,
+        erosion: &NoiseMap,
+        peaks: &NoiseMap,
+        range: VerticalRange,
+        settings: &ShapingSettings,
+    ) -> Result<Self, WorldGenError> {
+        settings.validate()?;
+        range.validate()?;
+
+        let (height, width) = (continentalness.get_height(), continentalness.get_width());
+        for map in [erosion, peaks] {
+            if (map.get_height(), map.get_width()) != (height, width) {
+                return Err(WorldGenError::DimensionMismatch {
+                    expected: (height, width),
+                    found: (map.get_height(), map.get_width()),
+                });
+            }
+        }
+        // Every value is read below, so make sure they are there
+        for map in [continentalness, erosion, peaks] {
+            map.try_get_value(0, 0)?;
+        }
+
+        Ok(HeightMap::from_fn(height, width, range, |row, column| {
+            let offset = settings.shape(
+                continentalness.get_value(row, column),
+                erosion.get_value(row, column),
+                peaks.get_value(row, column),
+            );
+            range.height_at_offset(offset)
+        }))
+    }
+
+    /// Generates every layer of noise for the region, and shapes a HeightMap from them.
+    pub fn generate(
+        region: Region,
+        range: VerticalRange,
+        settings: &ShapingSettings,
+        seed: WorldSeed,
+    ) -> Result<Self, WorldGenError> {
+        let continentalness = NoiseMap::from_settings(region, &settings.continentalness, seed.derive("continentalness"))?;
+        let erosion = NoiseMap::from_settings(region, &settings.erosion, seed.derive("erosion"))?;
+        let peaks = NoiseMap::from_settings(region, &settings.peaks, seed.derive("peaks and valleys"))?;
+
+        HeightMap::from_shaped_noise(&continentalness, &erosion, &peaks, range, settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_gen::terrain::terrain_type::{TerrainRegistry, TerrainType};
+
+    const SEED: WorldSeed = WorldSeed::from_u64(0x5EED);
+
+    #[test]
+    fn erosion_flattens_the_peaks() {
+        let settings = ShapingSettings::default();
+
+        let rough = settings.shape(0.7, 0.0, 1.0);
+        let eroded = settings.shape(0.7, 1.0, 1.0);
+        assert!(rough > eroded);
+        assert_eq!(eroded, settings.continentalness_curve.sample(0.7));
+    }
+
+    #[test]
+    fn curves_can_be_used_as_mappers() {
+        let range = VerticalRange { min: -64, max: 320, sea_level: 62 };
+        let curve = Spline::linear(vec![(0.0, -1.0), (0.5, 0.0), (1.0, 1.0)]).unwrap();
+        let n_map = NoiseMap::from_settings(Region::new((0, 0), 16, 16), &NoiseSettings::default(), SEED).unwrap();
+
+        let height_map = HeightMap::from_noise_map(&n_map, range, |v| range.height_at_offset(curve.sample(v)));
+        for row in 0..16 {
+            for column in 0..16 {
+                let height = height_map.get(row, column);
+                let value = n_map.get_value(row, column);
+                assert!(range.contains(height));
+                assert!(height <= range.sea_level || value > 0.5);
+                assert!(height >= range.sea_level || value < 0.5);
+            }
+        }
+    }
+
+    #[test]
+    fn shaped_terrain_has_oceans_and_mountains() {
+        let range = VerticalRange { min: -64, max: 320, sea_level: 62 };
+        let height_map = HeightMap::generate(Region::new((0, 0), 256, 128), range, &ShapingSettings::default(), SEED).unwrap();
+        let registry = TerrainRegistry::default();
+
+        let mut kinds = Vec::new();
+        for row in 0..height_map.get_height() {
+            for column in 0..height_map.get_width() {
+                kinds.push(height_map.terrain(&registry, row, column).unwrap().kind);
+            }
+        }
+        assert!(kinds.contains(&TerrainType::Ocean));
+        assert!(kinds.contains(&TerrainType::HighLand));
+    }
+
+    #[test]
+    fn mismatched_layers_are_rejected() {
+        let settings = ShapingSettings::default();
+        let small = NoiseMap::from_settings(Region::new((0, 0), 8, 8), &settings.erosion, SEED).unwrap();
+        let large = NoiseMap::from_settings(Region::new((0, 0), 8, 16), &settings.erosion, SEED).unwrap();
+
+        assert!(matches!(
+            HeightMap::from_shaped_noise(&small, &large, &small, VerticalRange::default(), &settings),
+            Err(WorldGenError::DimensionMismatch { .. })
+        ));
+
+        let broken = ShapingSettings { peaks_curve: Spline { points: Vec::new(), ..settings.peaks_curve.clone() }, ..settings };
+        assert_eq!(
+            HeightMap::from_shaped_noise(&small, &small, &small, VerticalRange::default(), &broken).err(),
+            Some(WorldGenError::InvalidSpline("peaks curve"))
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! Curves for remapping noise values, e.g. into heights.
+//!
+//! A straight line from noise to height makes every part of the world look the same,
+//! a curve can keep plains flat and still make coasts and mountains steep.
+
+use serde::{Deserialize, Serialize};
+
+use crate::world_gen::error::WorldGenError;
+
+/// How a [`Spline`] gets from one point to the next.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    /// Straight lines between the points.
+    Linear,
+
+    /// Smooth curves through the points, which never overshoot them (monotone cubic Hermite).
+    ///
+    /// So a curve between two points never goes above the higher, or below the lower, of them.
+    #[default]
+    Cubic,
+}
+
+/// A curve through a list of (input, output) points.
+///
+/// Inputs before the first point, or after the last, get the output of that point.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Spline {
+    pub points: Vec<(f64, f64)>,    // Sorted by input
+    #[serde(default)]
+    pub interpolation: Interpolation,
+}
+
+impl Spline {
+    /// Creates a spline through the given points.
+    ///
+    /// # Errors
+    /// Returns an error if the spline is invalid, see [`validate`](Self::validate).
+    pub fn new(points: Vec<(f64, f64)>, interpolation: Interpolation) -> Result<Self, WorldGenError> {
+        let spline = Spline { points, interpolation };
+        spline.validate()?;
+        Ok(spline)
+    }
+
+    /// Creates a spline with straight lines between the given points.
+    pub fn linear(points: Vec<(f64, f64)>) -> Result<Self, WorldGenError> {
+        Spline::new(points, Interpolation::Linear)
+    }
+
+    /// Creates a smooth spline through the given points.
+    pub fn cubic(points: Vec<(f64, f64)>) -> Result<Self, WorldGenError> {
+        Spline::new(points, Interpolation::Cubic)
+    }
+
+    /// Returns an error if there are no points, any point isn't finite,
+    /// or the inputs of the points aren't strictly increasing.
+    pub fn validate(&self) -> Result<(), WorldGenError> {
+        let finite = self.points.iter().all(|(x, y)| x.is_finite() && y.is_finite());
+        let sorted = self.points.windows(2).all(|pair| pair[0].0 < pair[1].0);
+
+        match !self.points.is_empty() && finite && sorted {
+            true => Ok(()),
+            false => Err(WorldGenError::InvalidSpline("spline")),
+        }
+    }
+
+    /// Returns the output of the curve at the given input.
+    ///
+    /// # Panics
+    /// Panics if the spline has no points.
+    pub fn sample(&self, x: f64) -> f64 {
+        let points = &self.points;
+        let last = points.len() - 1;
+        if x <= points[0].0 {
+            return points[0].1;
+        }
+        if x >= points[last].0 {
+            return points[last].1;
+        }
+
+        // The segment [k; k + 1] the input is in
+        let k = points.partition_point(|&(px, _)| px <= x) - 1;
+        let ((x0, y0), (x1, y1)) = (points[k], points[k + 1]);
+        let h = x1 - x0;
+        let t = (x - x0) / h;
+
+        match self.interpolation {
+            Interpolation::Linear => y0 + (y1 - y0) * t,
+            Interpolation::Cubic => {
+                let (m0, m1) = (self.tangent(k), self.tangent(k + 1));
+
+                let t2 = t * t;
+                let t3 = t2 * t;
+                (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+                    + (t3 - 2.0 * t2 + t) * h * m0
+                    + (-2.0 * t3 + 3.0 * t2) * y1
+                    + (t3 - t2) * h * m1
+            }
+        }
+    }
+
+    /// The slope of the curve at point `k`.
+    ///
+    /// Inner points use the weighted harmonic mean of the slopes on either side (as in PCHIP),
+    /// which keeps the curve from overshooting. Points at a peak or valley are flat.
+    fn tangent(&self, k: usize) -> f64 {
+        let points = &self.points;
+        let slope = |i: usize| (points[i + 1].1 - points[i].1) / (points[i + 1].0 - points[i].0);
+        let width = |i: usize| points[i + 1].0 - points[i].0;
+
+        if k == 0 {
+            return slope(0);
+        }
+        if k == points.len() - 1 {
+            return slope(k - 1);
+        }
+
+        let (before, after) = (slope(k - 1), slope(k));
+        if before * after <= 0.0 {
+            return 0.0;
+        }
+        let w1 = 2.0 * width(k) + width(k - 1);
+        let w2 = width(k) + 2.0 * width(k - 1);
+        (w1 + w2) / (w1 / before + w2 / after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POINTS: [(f64, f64); 5] = [(0.0, 0.0), (0.2, 0.1), (0.5, 0.15), (0.6, 0.8), (1.0, 1.0)];
+
+    #[test]
+    fn splines_pass_through_their_points() {
+        for spline in [Spline::linear(POINTS.to_vec()).unwrap(), Spline::cubic(POINTS.to_vec()).unwrap()] {
+            for (x, y) in POINTS {
+                assert!((spline.sample(x) - y).abs() < 1e-12);
+            }
+            assert_eq!(spline.sample(-1.0), 0.0);
+            assert_eq!(spline.sample(2.0), 1.0);
+        }
+
+        let linear = Spline::linear(POINTS.to_vec()).unwrap();
+        assert!((linear.sample(0.55) - 0.475).abs() < 1e-12);
+    }
+
+    #[test]
+    fn cubic_splines_dont_overshoot() {
+        let spline = Spline::cubic(POINTS.to_vec()).unwrap();
+
+        let mut previous = spline.sample(0.0);
+        for i in 1..=1000 {
+            let value = spline.sample(i as f64 / 1000.0);
+            assert!(value >= previous);
+            previous = value;
+        }
+
+        // A peak stays a peak
+        let peak = Spline::cubic(vec![(0.0, 0.0), (0.5, 1.0), (1.0, 0.0)]).unwrap();
+        assert!((0..=100).all(|i| peak.sample(i as f64 / 100.0) <= 1.0));
+    }
+
+    #[test]
+    fn invalid_splines_are_rejected() {
+        assert!(Spline::linear(Vec::new()).is_err());
+        assert!(Spline::linear(vec![(0.5, 0.0), (0.5, 1.0)]).is_err());
+        assert!(Spline::cubic(vec![(1.0, 0.0), (0.0, 1.0)]).is_err());
+        assert!(Spline::cubic(vec![(0.0, f64::NAN)]).is_err());
+
+        let single = Spline::cubic(vec![(0.3, 0.7)]).unwrap();
+        assert_eq!(single.sample(0.9), 0.7);
+    }
+
+    #[test]
+    fn splines_load_from_ron() {
+        let spline: Spline = ron::from_str("(points: [(0.0, 1.0), (1.0, 3.0)], interpolation: linear)").unwrap();
+        assert_eq!(spline.sample(0.5), 2.0);
+
+        let cubic: Spline = ron::from_str("(points: [(0.0, 1.0)])").unwrap();
+        assert_eq!(cubic.interpolation, Interpolation::Cubic);
+    }
+}
@@ -0,0 +1,148 @@
+//! Layers describing the shape of the surface of a HeightMap, like how steep it is and which way it faces.
+//!
+//! Every cell is treated as being one height unit wide, so a slope of 45° rises one height per cell.
+//! Row 0 is the north edge of the map, and column 0 the west edge.
+
+use image::Rgb;
+
+use crate::world_gen::grid::Grid2D;
+use super::height_map::HeightMap;
+
+/// Methods for deriving the surface of HeightMaps.
+impl HeightMap {
+    /// Returns how fast the height changes at every cell, as (east, south) per cell.
+    ///
+    /// Uses central differences, and one-sided differences along the edges of the map,
+    /// so the border cells get a real gradient instead of being flat.
+    pub fn gradient(&self) -> Grid2D<(f64, f64)> {
+        let heights = self.grid().map(|&height| height as f64);
+        let (height, width) = heights.size();
+
+        Grid2D::from_fn(height, width, |row, column| {
+            let east = difference(column, width, |c| heights[(row, c)]);
+            let south = difference(row, height, |r| heights[(r, column)]);
+            (east, south)
+        })
+    }
+
+    /// Returns the steepness of every cell, in degrees from flat (0°) to vertical (90°).
+    pub fn slope(&self) -> Grid2D<f64> {
+        self.gradient().map(|&(east, south)| east.hypot(south).atan().to_degrees())
+    }
+
+    /// Returns the direction every cell faces (the way down the slope), in degrees clockwise from north.
+    ///
+    /// Flat cells don't face any direction, and are None.
+    pub fn aspect(&self) -> Grid2D<Option<f64>> {
+        self.gradient().map(|&(east, south)| {
+            if east == 0.0 && south == 0.0 {
+                return None;
+            }
+            // Downhill is against the gradient
+            let (down_east, down_north) = (-east, south);
+            Some(down_east.atan2(down_north).to_degrees().rem_euclid(360.0))
+        })
+    }
+
+    /// Returns the unit normal of the surface at every cell, as [east, north, up].
+    ///
+    /// Heights are multiplied by `exaggeration` first, which makes gentle terrain easier to see.
+    pub fn normals(&self, exaggeration: f64) -> Grid2D<[f64; 3]> {
+        self.gradient().map(|&(east, south)| {
+            let normal = [-east * exaggeration, south * exaggeration, 1.0];
+            let length = normal.iter().map(|n| n * n).sum::<f64>().sqrt();
+            normal.map(|n| n / length)
+        })
+    }
+}
+
+/// Encodes a unit normal as a colour, the way normal maps usually are: 
+/// red is east, green is north and blue is up, each mapped from [-1; 1] to [0; 255].
+/// 
+/// So flat ground is (128, 128, 255), the familiar light blue of normal maps.
+pub fn normal_to_rgb(normal: [f64; 3]) -> Rgb<u8> {
+    Rgb(normal.map(|n| ((n.clamp(-1.0, 1.0) + 1.0) / 2.0 * u8::MAX as f64).round() as u8))
+}
+
+/// The change in value per cell at position `i` along a line of `len` cells,
+/// where `value(i)` is the value of cell `i`.
+///
+/// Central difference inside the line, one-sided at the ends, and 0 for a line of a single cell.
+fn difference(i: usize, len: usize, value: impl Fn(usize) -> f64) -> f64 {
+    match i {
+        _ if len < 2 => 0.0,
+        0 => value(1) - value(0),
+        _ if i == len - 1 => value(i) - value(i - 1),
+        _ => (value(i + 1) - value(i - 1)) / 2.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_gen::terrain::height_map::{Height, VerticalRange};
+
+    const RANGE: VerticalRange = VerticalRange { min: -1000, max: 1000, sea_level: 0 };
+
+    /// A 4x7 plane rising `east` heights per column, and `south` per row.
+    fn plane(east: Height, south: Height) -> HeightMap {
+        HeightMap::from_fn(4, 7, RANGE, |row, column| row as Height * south + column as Height * east)
+    }
+
+    #[test]
+    fn planes_have_the_same_slope_everywhere() {
+        let slope = plane(1, 0).slope();
+        assert!(slope.iter().all(|&degrees| (degrees - 45.0).abs() < 1e-9));
+
+        let gradient = plane(2, -3).gradient();
+        assert!(gradient.iter().all(|&g| g == (2.0, -3.0)));
+
+        assert!(plane(0, 0).slope().iter().all(|&degrees| degrees == 0.0));
+    }
+
+    #[test]
+    fn aspect_faces_downhill() {
+        let facing = |east, south| plane(east, south).aspect()[(2, 3)];
+
+        assert_eq!(facing(0, 0), None);
+        assert_eq!(facing(0, 1), Some(0.0));    // Rising to the south, so facing north
+        assert_eq!(facing(-1, 0), Some(90.0));
+        assert_eq!(facing(0, -1), Some(180.0));
+        assert_eq!(facing(1, 0), Some(270.0));
+        assert_eq!(facing(-1, 1), Some(45.0));
+
+        // Corners use one-sided differences, but still face the same way
+        assert_eq!(plane(0, -1).aspect()[(3, 6)], Some(180.0));
+    }
+
+    #[test]
+    fn normals_are_unit_length_and_lean_downhill() {
+        let normals = plane(-1, 0).normals(1.0);
+        for normal in normals.iter() {
+            let length = normal.iter().map(|n| n * n).sum::<f64>().sqrt();
+            assert!((length - 1.0).abs() < 1e-12);
+            assert!(normal[0] > 0.0 && normal[1] == 0.0);
+        }
+
+        assert_eq!(plane(0, 0).normals(5.0)[(0, 0)], [0.0, 0.0, 1.0]);
+        assert!(plane(0, 1).normals(3.0)[(0, 0)][1] > plane(0, 1).normals(1.0)[(0, 0)][1]);
+    }
+
+    #[test]
+    fn normals_are_encoded_as_colours() {
+        assert_eq!(normal_to_rgb([0.0, 0.0, 1.0]), Rgb([128, 128, 255]));
+        assert_eq!(normal_to_rgb([-1.0, 1.0, 0.0]), Rgb([0, 255, 128]));
+
+        let leaning_east = normal_to_rgb(plane(-1, 0).normals(1.0)[(0, 0)]);
+        assert!(leaning_east[0] > 128 && leaning_east[1] == 128);
+    }
+
+    #[test]
+    fn thin_maps_are_handled() {
+        let line = HeightMap::from_fn(1, 5, RANGE, |_, column| column as Height);
+        assert!(line.gradient().iter().all(|&g| g == (1.0, 0.0)));
+
+        let cell = HeightMap::from_fn(1, 1, RANGE, |_, _| 7);
+        assert_eq!(cell.slope()[(0, 0)], 0.0);
+    }
+}
@@ -1,218 +1,398 @@
 /// Not sure if we will actually end up using this (maybe), but it should help
 /// make working with the 2D texture things easier.
-/// 
-/// The error handling here isn't perfect, but it should be plenty for this.
+///
+/// The different classes of terrain used to live in hardcoded constants,
+/// they are now loaded from a data file into a [`TerrainRegistry`], so new ones can be added without recompiling.
+
+use std::fs;
+use std::path::Path;
 
-use std::ops::Range;
 use image::Rgb;
+use serde::{Deserialize, Serialize};
 
-use terrain_data::meta_data as meta_data;
+use crate::world_gen::error::WorldGenError;
+use super::biome::Biome;
 
 /// The type of the height value
 use super::height_map::Height;
 
-/// All different types of terrain.
-/// 
-/// The 'None' variant is not a valid TerrainType. Any method called on it will result in panic.
-/// The intention of [`TerrainType::None`] is for potentially recoverable errors.
-/// 
-/// Maybe use a HashMap to point from variant to constant?
-#[derive(Debug, Copy, Clone)]
+/// The kinds of terrain the world generation knows how to treat differently.
+///
+/// Every [`TerrainClass`] is one of these kinds, but there can be any number of classes of the same kind,
+/// e.g. several shades of high land.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TerrainType {
     DeepOcean,
     Ocean,
     Beach,
     LowLand,
     HighLand,
-
-    #[allow(dead_code)]
-    None,
+    River,
+    Lake,
 }
 
-/// Identify terrain
 impl TerrainType {
-    /// Identifies the TerrainType whose range contains the given height
-    /// 
-    /// # Panics
-    /// 
-    /// Height values not contained in any TerrainType range are invalid.
-    pub fn ident(height: &Height) -> TerrainType {
-        // Assert that height is within valid bounds according to MAX_VALUE 
-        assert!(
-            height <= &meta_data::MAX_VALUE,
-            "\n
-            INVALID HEIGHT VALUE:\n
-            Parsed height = {:?}\tLargest valid height value = {}\n
-            \t  Cannot identify TerrainType based on invalid value! ({:?} > {})\n
-            ", height, meta_data::MAX_VALUE, height, meta_data::MAX_VALUE,
-        );
-
-        // Assert that height is within valid bounds according to MIN_VALUE
-        assert!(
-            height >= &meta_data::MIN_VALUE,
-            "\n
-            INVALID HEIGHT VALUE:\n
-            Parsed height = {:?}\tSmallest valid height value = {}\n
-            \t  Cannot identify TerrainType based on invalid value! ({:?} <) {}\n
-            ", height, meta_data::MIN_VALUE, height, meta_data::MIN_VALUE,
-        );
+    /// Returns true for the kinds of terrain that are found from the flow of water instead of the height.
+    pub fn is_overlay(&self) -> bool {
+        matches!(self, TerrainType::River | TerrainType::Lake)
+    }
 
-        // Not sure if there is a better way of doing this, but this is what i came up with
-        match height {
-            _ if terrain_data::DEEP_OCEAN.contains(height)  => Self::DeepOcean,
-            _ if terrain_data::OCEAN.contains(height)       => Self::Ocean,
-            _ if terrain_data::BEACH.contains(height)       => Self::Beach,
-            _ if terrain_data::LOW_LAND.contains(height)    => Self::LowLand,
-            _ if terrain_data::HIGH_LAND.contains(height)   => Self::HighLand,
-
-            _ => {  // I can't imagine recovery from here ever being a good idea, panic seems appropriate
-                panic!("\n
-                INVALID HEIGHT VALUE:\n
-                Could not identify a valid TerrainType given value {:?}.\n
-                \t  It is possible a TerrainType has been excluded from the `crate::world_gen::terrain::terrain_type::TerrainType::ident()` method\n
-                ",
-                height
-                )
-            }
+    /// Returns the name of the kind, as written in data files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TerrainType::DeepOcean => "deep_ocean",
+            TerrainType::Ocean     => "ocean",
+            TerrainType::Beach     => "beach",
+            TerrainType::LowLand   => "low_land",
+            TerrainType::HighLand  => "high_land",
+            TerrainType::River     => "river",
+            TerrainType::Lake      => "lake",
         }
     }
 }
 
-/// Data-relevant methods for TerrainType's
-impl TerrainType {
-    /// Returns the assosiated colour of the TerrainType variant.
-    /// 
-    /// # Panics
+/// A single class of terrain, as defined in a data file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TerrainClass {
+    pub name: String,
+    pub kind: TerrainType,
+
+    /// The heights covered by the class are [`min`; `max`[, measured from sea level.
+    /// A missing bound means the class reaches all the way to the bottom (or top) of the world,
+    /// so the same classes work for worlds of any height.
     /// 
-    /// Parsing the [`None`](TerrainType::None) variant will panic.
-    pub fn colour(&self) -> Rgb<u8> {
-        match self {
-            Self::DeepOcean => terrain_data::DEEP_OCEAN.colour,
-            Self::Ocean     => terrain_data::OCEAN.colour,
-            Self::Beach     => terrain_data::BEACH.colour,
-            Self::LowLand   => terrain_data::LOW_LAND.colour,
-            Self::HighLand  => terrain_data::HIGH_LAND.colour,
-
-            Self::None  => {
-                panic!("\n{}{}{}{}",
-                    "INVALID METHOD CALL:\n",
-                    "Attempted to get colour of TerrainType::None\n",
-                    "\tThe None variant represents the lack of a type, as such,",
-                    "\tit is invalid for methods accessing data related to a TerrainType.\n",
-                );
-            }
+    /// Classes of rivers and lakes don't cover any heights, so they have neither.
+    #[serde(default)]
+    pub min: Option<Height>,
+    #[serde(default)]
+    pub max: Option<Height>,
+
+    pub colour: [u8; 3],
+
+    /// The colour at the top of the class, for renderers that fade between the two.
+    /// If missing, the class is a single colour.
+    #[serde(default)]
+    pub top_colour: Option<[u8; 3]>,
+
+    /// The biomes the class is found in. If empty, the class is found everywhere.
+    ///
+    /// Classes limited to some biomes take priority over the classes found everywhere.
+    #[serde(default)]
+    pub biomes: Vec<Biome>,
+}
+
+impl TerrainClass {
+    /// Returns true if the class covers the given height above sea level.
+    pub fn contains(&self, height: &Height) -> bool {
+        !self.kind.is_overlay()
+            && !matches!(self.min, Some(min) if *height < min)
+            && !matches!(self.max, Some(max) if *height >= max)
+    }
+
+    /// Returns true if the two classes cover any of the same heights.
+    fn overlaps(&self, other: &TerrainClass) -> bool {
+        let below = |max: Option<Height>, min: Option<Height>| match (max, min) {
+            (Some(max), Some(min)) => max <= min,
+            _ => false,
+        };
+        !below(self.max, other.min) && !below(other.max, self.min)
+    }
+
+    /// Returns true if the class can be found in the given biome.
+    fn allows(&self, biome: Option<Biome>) -> bool {
+        match biome {
+            Some(biome) => self.biomes.is_empty() || self.biomes.contains(&biome),
+            None => self.biomes.is_empty(),
         }
     }
 
-    /// Returns true if the value is within the scope of a TerrainType variant.
-    /// 
-    /// It's just nicer to write it like this, don't actually know if we need this method.
-    pub fn _contains(&self, value: &Height) -> bool {
-        match self {
-            Self::DeepOcean
-                => terrain_data::DEEP_OCEAN.range.contains(value),
-            Self::Ocean 
-                => terrain_data::OCEAN.range.contains(value),
-            Self::Beach
-                => terrain_data::BEACH.range.contains(value),
-            Self::LowLand
-                => terrain_data::LOW_LAND.range.contains(value),
-            Self::HighLand
-                => terrain_data::HIGH_LAND.range.contains(value),
-            
-            Self::None => {
-                panic!("\n{}{}{:?}{}{}{}",
-                    "INVALID METHOD CALL:\n",
-                    "Attempted to range match value ", value, " on TerrainType::None\n",
-                        "\tThe None variant represents the lack of a type, as such,",
-                        "\tit is invalid for methods accessing data related to a TerrainType.\n",
-                );   
-            }
-        }        
+    /// Returns the assosiated colour of the class.
+    pub fn colour(&self) -> Rgb<u8> {
+        Rgb(self.colour)
+    }
+
+    /// Returns the colour of the class `fraction` of the way from the bottom (0) to the top (1) of its heights.
+    pub fn colour_at(&self, fraction: f64) -> Rgb<u8> {
+        let top = self.top_colour.unwrap_or(self.colour);
+        let t = fraction.clamp(0.0, 1.0);
+
+        let mut colour = self.colour;
+        for (channel, top) in colour.iter_mut().zip(top) {
+            *channel = (*channel as f64 + (top as f64 - *channel as f64) * t).round() as u8;
+        }
+        Rgb(colour)
     }
 }
 
-/// Generic type to hold relevant data to define different types of terrain.
-pub struct TypeData {
-    range: Range<Height>,
-    colour: Rgb<u8>,
+/// Every class of terrain, and the heights they cover.
+///
+/// The classes found everywhere must cover every height exactly once, from the bottom to the top of the world,
+/// which is checked when the registry is loaded.
+/// 
+/// All heights are measured from sea level, so the registry works for any [`VerticalRange`](super::height_map::VerticalRange).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TerrainRegistry {
+    pub classes: Vec<TerrainClass>,
 }
 
-impl TypeData {
-    fn contains(&self, value: &Height) -> bool {
-        self.range.contains(value)
+impl TerrainRegistry {
+    /// Returns an error if the classes leave gaps between heights, overlap,
+    /// or there is no class for rivers and lakes.
+    pub fn validate(&self) -> Result<(), WorldGenError> {
+        for class in &self.classes {
+            let valid = match (class.min, class.max) {
+                _ if class.kind.is_overlay() => class.min.is_none() && class.max.is_none(),
+                (Some(min), Some(max)) => min < max,
+                _ => true,
+            };
+            if !valid {
+                return Err(WorldGenError::InvalidTerrainClass(class.name.clone()));
+            }
+        }
+
+        // The classes found everywhere must line up, from the bottom to the top.
+        // Sorting puts a class without a bottom first
+        let mut base: Vec<&TerrainClass> = self.classes
+            .iter()
+            .filter(|class| !class.kind.is_overlay() && class.biomes.is_empty())
+            .collect();
+        base.sort_by_key(|class| class.min);
+
+        let mut covered = None;     // The top of the classes so far, None is the bottom of the world
+        for (i, class) in base.iter().enumerate() {
+            if i > 0 && covered.is_none() {
+                return Err(WorldGenError::TerrainOverlap(class.name.clone()));  // Something already reached the top
+            }
+            match (covered, class.min) {
+                (covered, min) if covered == min => (),
+                (None, Some(min)) => return Err(WorldGenError::TerrainGap { from: Height::MIN, to: min }),
+                (Some(covered), Some(min)) if min > covered => return Err(WorldGenError::TerrainGap { from: covered, to: min }),
+                _ => return Err(WorldGenError::TerrainOverlap(class.name.clone())),
+            }
+            covered = class.max;
+        }
+        match (base.is_empty(), covered) {
+            (true, _) => return Err(WorldGenError::TerrainGap { from: Height::MIN, to: Height::MAX }),
+            (false, Some(top)) => return Err(WorldGenError::TerrainGap { from: top, to: Height::MAX }),
+            (false, None) => (),
+        }
+
+        // Classes limited to some biomes may overlap the ones found everywhere, but not each other
+        let limited: Vec<&TerrainClass> = self.classes
+            .iter()
+            .filter(|class| !class.kind.is_overlay() && !class.biomes.is_empty())
+            .collect();
+        for (i, first) in limited.iter().enumerate() {
+            for second in &limited[i + 1..] {
+                let share_biome = first.biomes.iter().any(|biome| second.biomes.contains(biome));
+                if share_biome && first.overlaps(second) {
+                    return Err(WorldGenError::TerrainOverlap(second.name.clone()));
+                }
+            }
+        }
+
+        for kind in [TerrainType::River, TerrainType::Lake] {
+            self.overlay(kind)?;
+        }
+        Ok(())
+    }
+
+    /// Parses and validates terrain types written in RON.
+    pub fn from_ron(ron: &str) -> Result<Self, WorldGenError> {
+        let registry: TerrainRegistry = ron::from_str(ron)
+            .map_err(|e| WorldGenError::Parse(e.to_string()))?;
+
+        registry.validate()?;
+        Ok(registry)
+    }
+
+    /// Loads and validates terrain types from a RON file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, WorldGenError> {
+        let ron = fs::read_to_string(path)
+            .map_err(|e| WorldGenError::Io(e.to_string()))?;
+
+        TerrainRegistry::from_ron(&ron)
+    }
+
+    /// Identifies the class covering the given height above sea level, in any biome.
+    ///
+    /// # Errors
+    ///
+    /// Height values not covered by any class are invalid,
+    /// and return [`WorldGenError::UnknownHeight`].
+    pub fn ident(&self, height: &Height) -> Result<&TerrainClass, WorldGenError> {
+        self.ident_in(height, None)
+    }
+
+    /// Identifies the class covering the given height above sea level in the given biome.
+    ///
+    /// Classes limited to the biome are picked over the classes found everywhere.
+    pub fn ident_in(&self, height: &Height, biome: Option<Biome>) -> Result<&TerrainClass, WorldGenError> {
+        let mut found = None;
+        for class in self.classes.iter().filter(|class| class.contains(height) && class.allows(biome)) {
+            if !class.biomes.is_empty() {
+                return Ok(class);
+            }
+            found = found.or(Some(class));
+        }
+        found.ok_or(WorldGenError::UnknownHeight(*height))
+    }
+
+    /// Returns the class used for terrain of the given kind,
+    /// which isn't identified by height (like rivers).
+    ///
+    /// # Errors
+    /// Kinds identified by height, and overlays missing from the registry, return [`WorldGenError::MissingTerrain`].
+    pub fn overlay(&self, kind: TerrainType) -> Result<&TerrainClass, WorldGenError> {
+        self.classes
+            .iter()
+            .find(|class| class.kind == kind && class.kind.is_overlay())
+            .ok_or(WorldGenError::MissingTerrain(kind.name()))
     }
 }
 
-/// Data defining the different terrain types.
-/// 
-/// I did try making this work with [`Range`] and [`RangeInclusive`]
-/// as to not exclude [`MAX_VALUE`], which is a valid value, but this ended up causing a couple problems i was unable to solve in a nice way. 
-/// However, with the addition of [`HeightMap`], simply sticking to [`Range`] is possible as it lets us handle the noise values as integers. 
-/// That still isn't perfect, but it's a lot better than before.
-/// 
-/// [`Range`]: std::ops::Range
-/// [`RangeInclusive`]: std::ops::RangeInclusive
-/// [`HeightMap`]: crate::world_gen::terrain::height_map::HeightMap
-/// [`MAX_VALUE`]: meta_data::MAX_VALUE
-mod terrain_data {
-    use image::Rgb;
-
-    use super::TypeData;
-    use super::Height;
-    use meta_data::*;
-
-    /// Data to define valid values for TypeData constants
-    pub(super) mod meta_data {
-        use super::Height;
-        
-        /// The smallest, valid, value for the range of a terrain type
-        pub const MIN_VALUE: Height = 0;
-
-        /// The largest, valid, value for the range of a terrain type.
-        /// 
-        /// Range is not inclusive on the maximum, so we +1 to allow all values. 
-        /// This addition is written excplicitly to make this clearer.
-        pub const MAX_VALUE: Height =  100 + 1;
-    }
-
-    pub const DEEP_OCEAN: TypeData = TypeData {
-        range:  MIN_VALUE..35,
-        colour: Rgb([ 15,  82, 186]),
-    };
-
-    pub const OCEAN: TypeData = TypeData {
-        range:  35..45,
-        colour: Rgb([ 65, 105, 225]),
-    };
-
-    pub const BEACH: TypeData = TypeData {
-        range:  45..50,
-        colour: Rgb([194, 178, 128]),
-    };
-
-    pub const LOW_LAND: TypeData = TypeData {
-        range:  50..55,
-        colour: Rgb([ 19, 133,  16]),
-    };
-
-    pub const HIGH_LAND: TypeData = TypeData {
-        range:  55..MAX_VALUE,
-        colour: Rgb([ 19, 109,  21]),
-    };
+impl Default for TerrainRegistry {
+    /// The terrain types shipped with the game (`assets/world_gen/terrain_types.ron`).
+    fn default() -> Self {
+        TerrainRegistry::from_ron(include_str!("../../../assets/world_gen/terrain_types.ron"))
+            .expect("The built-in terrain types are invalid")
+    }
 }
 
-/// # TESTS TO ADD:
-/// 
-/// Are all range consts valid? (are they all within the accepted range? do any of them overlap?)
-/// 
-/// `from_range()` method output is valid and as expected for ALL ranges?
-/// 
-/// `ident()` method returns the expected TerrainType (also test the asserts)
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    /// A registry with the given classes found everywhere, plus rivers and lakes.
+    fn registry(bounds: &[(Option<Height>, Option<Height>)]) -> TerrainRegistry {
+        let class = |name: &str, kind, (min, max)| TerrainClass {
+            name: name.to_string(),
+            kind,
+            min,
+            max,
+            colour: [0, 0, 0],
+            top_colour: None,
+            biomes: Vec::new(),
+        };
+
+        let mut classes: Vec<TerrainClass> = bounds
+            .iter()
+            .map(|&bounds| class("land", TerrainType::LowLand, bounds))
+            .collect();
+        classes.push(class("river", TerrainType::River, (None, None)));
+        classes.push(class("lake", TerrainType::Lake, (None, None)));
+
+        TerrainRegistry { classes }
+    }
+
+    #[test]
+    fn ident_rejects_invalid_heights() {
+        let default = TerrainRegistry::default();
+        assert_eq!(default.ident(&-1000).map(|class| class.kind), Ok(TerrainType::DeepOcean));
+        assert_eq!(default.ident(&-1).map(|class| class.kind), Ok(TerrainType::Ocean));
+        assert_eq!(default.ident(&0).map(|class| class.kind), Ok(TerrainType::Beach));
+        assert_eq!(default.ident(&1000).map(|class| class.kind), Ok(TerrainType::HighLand));
+
+        // Only a registry that hasn't been validated can have holes in it
+        let holes = registry(&[(None, Some(0)), (Some(10), None)]);
+        assert_eq!(holes.ident(&5), Err(WorldGenError::UnknownHeight(5)));
+    }
+
     #[test]
     fn terrain_type_range_validation() {
-        unimplemented!();
+        assert_eq!(TerrainRegistry::default().validate(), Ok(()));
+        assert_eq!(registry(&[(None, Some(0)), (Some(0), None)]).validate(), Ok(()));
+        assert_eq!(registry(&[(Some(0), None), (None, Some(0))]).validate(), Ok(()));
+
+        assert_eq!(
+            registry(&[(None, Some(0)), (Some(5), None)]).validate(), 
+            Err(WorldGenError::TerrainGap { from: 0, to: 5 }),
+        );
+        assert_eq!(
+            registry(&[(None, Some(0)), (Some(0), Some(90))]).validate(), 
+            Err(WorldGenError::TerrainGap { from: 90, to: Height::MAX }),
+        );
+        assert_eq!(
+            registry(&[(Some(-10), Some(0)), (Some(0), None)]).validate(), 
+            Err(WorldGenError::TerrainGap { from: Height::MIN, to: -10 }),
+        );
+        assert_eq!(
+            registry(&[(None, Some(5)), (Some(0), None)]).validate(), 
+            Err(WorldGenError::TerrainOverlap("land".to_string())),
+        );
+        assert!(registry(&[(None, None), (Some(0), None)]).validate().is_err());
+        assert!(registry(&[(None, Some(0)), (Some(0), Some(0)), (Some(0), None)]).validate().is_err());
+
+        let mut no_lakes = registry(&[(None, None)]);
+        no_lakes.classes.retain(|class| class.kind != TerrainType::Lake);
+        assert_eq!(no_lakes.validate(), Err(WorldGenError::MissingTerrain("lake")));
+
+        let mut high_river = registry(&[(None, None)]);
+        high_river.classes[1].min = Some(10);
+        assert_eq!(high_river.validate(), Err(WorldGenError::InvalidTerrainClass("river".to_string())));
+    }
+
+    #[test]
+    fn biome_classes_take_priority() {
+        let mut desert = registry(&[(None, None)]);
+        desert.classes.push(TerrainClass {
+            name: "dunes".to_string(),
+            kind: TerrainType::HighLand,
+            min: Some(15),
+            max: None,
+            colour: [237, 201, 115],
+            top_colour: None,
+            biomes: vec![Biome::Desert],
+        });
+        assert_eq!(desert.validate(), Ok(()));
+
+        assert_eq!(desert.ident_in(&25, Some(Biome::Desert)).unwrap().name, "dunes");
+        assert_eq!(desert.ident_in(&5, Some(Biome::Desert)).unwrap().name, "land");
+        assert_eq!(desert.ident_in(&25, Some(Biome::Forest)).unwrap().name, "land");
+        assert_eq!(desert.ident(&25).unwrap().name, "land");
+
+        // Overlapping classes in the same biome are ambiguous
+        let mut overlapping = desert.clone();
+        overlapping.classes.push(TerrainClass { 
+            name: "oasis".to_string(), 
+            min: Some(5), 
+            max: Some(20), 
+            ..desert.classes[3].clone() 
+        });
+        assert_eq!(overlapping.validate(), Err(WorldGenError::TerrainOverlap("oasis".to_string())));
+    }
+
+    #[test]
+    fn classes_load_from_ron() {
+        let ron = r#"
+            #![enable(implicit_some)]
+            (
+                classes: [
+                    (name: "sea",   kind: ocean,    max: 0,     colour: (0, 0, 255)),
+                    (name: "land",  kind: low_land, min: 0,     colour: (0, 255, 0)),
+                    (name: "river", kind: river,    colour: (0, 0, 200)),
+                    (name: "lake",  kind: lake,     colour: (0, 0, 150)),
+                ],
+            )
+        "#;
+        let registry = TerrainRegistry::from_ron(ron).unwrap();
+
+        assert_eq!(registry.ident(&7).unwrap().colour(), Rgb([0, 255, 0]));
+        assert_eq!(registry.overlay(TerrainType::River).unwrap().name, "river");
+        assert_eq!(registry.overlay(TerrainType::LowLand), Err(WorldGenError::MissingTerrain("low_land")));
+        assert!(TerrainRegistry::from_ron(&ron.replace("min: 0,", "min: 1,")).is_err());
+    }
+
+    #[test]
+    fn colours_fade_to_the_top() {
+        let mut class = registry(&[(None, None)]).classes.remove(0);
+        class.colour = [0, 100, 200];
+        assert_eq!(class.colour_at(0.7), Rgb([0, 100, 200]));
+
+        class.top_colour = Some([100, 100, 0]);
+        assert_eq!(class.colour_at(0.0), Rgb([0, 100, 200]));
+        assert_eq!(class.colour_at(0.5), Rgb([50, 100, 100]));
+        assert_eq!(class.colour_at(2.0), Rgb([100, 100, 0]));
     }
 }
@@ -1,40 +1,172 @@
-/// This is a temp file, just to see if this works
+//! Turns maps into images, to preview them in game, in tests or as files.
+//!
+//! Nothing in here touches the file system, except for [`save_texture`], so the caller decides where images end up.
 
-use image::{
-    Rgb, ImageBuffer, 
-};
-use crate::world_gen::{noise::noise_map::NoiseMap, terrain::terrain_type::TerrainType};
+use std::path::Path;
 
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::texture::Image;
+use image::{DynamicImage, ImageBuffer, RgbImage};
+
+use crate::world_gen::error::WorldGenError;
+use super::biome::BiomeMap;
 use super::height_map::HeightMap;
+use super::surface::normal_to_rgb;
+use super::terrain_type::TerrainRegistry;
+
+/// Returns an image of the given HeightMap, one pixel per cell.
+///
+/// Every pixel is coloured by the class of terrain the registry identifies it as.
+/// Pixel (x, y) is the cell at row y and column x, so north is up.
+///
+/// See [`render`](super::render::render) for a nicer looking image.
+///
+/// # Errors
+/// Returns an error if the terrain of a cell can't be identified.
+pub fn terrain_texture(
+    height_map: &HeightMap,
+    registry: &TerrainRegistry,
+) -> Result<RgbImage, WorldGenError> {
+    terrain_texture_with(height_map, registry, None)
+}
+
+/// Returns an image of the given HeightMap, with the terrain of every cell identified in its biome.
+///
+/// Like [`terrain_texture`], but classes limited to a biome show up where the BiomeMap has that biome.
+///
+/// # Errors
+/// Returns an error if the BiomeMap isn't the same size as the HeightMap, or the terrain of a cell can't be identified.
+pub fn terrain_texture_in(
+    height_map: &HeightMap,
+    registry: &TerrainRegistry,
+    biome_map: &BiomeMap,
+) -> Result<RgbImage, WorldGenError> {
+    terrain_texture_with(height_map, registry, Some(biome_map))
+}
+
+fn terrain_texture_with(
+    height_map: &HeightMap,
+    registry: &TerrainRegistry,
+    biome_map: Option<&BiomeMap>,
+) -> Result<RgbImage, WorldGenError> {
+    if let Some(biome_map) = biome_map {
+        check_size(height_map, biome_map)?;
+    }
+
+    // Identify the terrain of every pixel up front, so an invalid height is reported instead of panicking
+    let mut colours = Vec::with_capacity(height_map.get_height() * height_map.get_width());
+    for y in 0..height_map.get_height() {
+        for x in 0..height_map.get_width() {
+            // y is the row, x is the column
+            let class = match biome_map {
+                Some(biome_map) => height_map.terrain_in(registry, y, x, biome_map)?,
+                None => height_map.terrain(registry, y, x)?,
+            };
+            colours.push(class.colour());
+        }
+    }
+
+    Ok(ImageBuffer::from_fn(
+        height_map.get_width() as u32,
+        height_map.get_height() as u32,
+        |x, y| {    // Describes the value of every pixel
+            colours[y as usize * height_map.get_width() + x as usize]
+        },
+    ))
+}
+
+/// Returns a normal map of the given HeightMap.
+///
+/// Every pixel is the normal of the surface (see [`HeightMap::normals`]), encoded as a colour by [`normal_to_rgb`].
+/// Pixels are laid out the same way as in [`terrain_texture`], so the two images line up.
+pub fn normal_texture(height_map: &HeightMap, exaggeration: f64) -> RgbImage {
+    let normals = height_map.normals(exaggeration);
+    ImageBuffer::from_fn(
+        height_map.get_width() as u32,
+        height_map.get_height() as u32,
+        |x, y| {    // Describes the value of every pixel
+            normal_to_rgb(normals[(y as usize, x as usize)])
+        },
+    )
+}
+
+/// Returns an image of the given BiomeMap.
+///
+/// Pixels are laid out the same way as in [`terrain_texture`], so the two images line up.
+pub fn biome_texture(biome_map: &BiomeMap) -> RgbImage {
+    ImageBuffer::from_fn(
+        biome_map.get_width() as u32,
+        biome_map.get_height() as u32,
+        |x, y| {    // Describes the value of every pixel
+            biome_map.get(y as usize, x as usize).colour()
+        },
+    )
+}
+
+/// Returns an error if the BiomeMap isn't the same size as the HeightMap.
+pub(super) fn check_size(height_map: &HeightMap, biome_map: &BiomeMap) -> Result<(), WorldGenError> {
+    let expected = (height_map.get_height(), height_map.get_width());
+    let found = (biome_map.get_height(), biome_map.get_width());
+    match expected == found {
+        true => Ok(()),
+        false => Err(WorldGenError::DimensionMismatch { expected, found }),
+    }
+}
+
+/// Saves the image at the given path, the format is chosen from the extension of the path.
+pub fn save_texture(img: &RgbImage, path: impl AsRef<Path>) -> Result<(), WorldGenError> {
+    img.save(path).map_err(|e| WorldGenError::Image(e.to_string()))
+}
+
+/// Converts the image into a Bevy [`Image`], which can be added to `Assets<Image>` and shown in game.
+///
+/// Any kind of image is accepted, e.g. the grayscale images of [`NoiseMap::to_image`](crate::world_gen::noise::noise_map::NoiseMap::to_image),
+/// they are all converted to 8bit RGBA in sRGB, which Bevy can always display.
+pub fn to_bevy_image(img: impl Into<DynamicImage>) -> Image {
+    let img = img.into().into_rgba8();
+    let size = Extent3d {
+        width: img.width(),
+        height: img.height(),
+        depth_or_array_layers: 1,
+    };
+
+    Image::new(size, TextureDimension::D2, img.into_raw(), TextureFormat::Rgba8UnormSrgb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_gen::terrain::height_map::{Height, VerticalRange};
+
+    const RANGE: VerticalRange = VerticalRange { min: 0, max: 100, sea_level: 45 };
+
+    #[test]
+    fn textures_line_up_with_the_map() {
+        let registry = TerrainRegistry::default();
+        // Deep ocean in the west, high land in the east
+        let height_map = HeightMap::from_fn(2, 5, RANGE, |_, column| column as Height * 25);
+
+        let terrain = terrain_texture(&height_map, &registry).unwrap();
+        assert_eq!(terrain.dimensions(), (5, 2));
+        assert_eq!(*terrain.get_pixel(0, 1), height_map.terrain(&registry, 1, 0).unwrap().colour());
+        assert_eq!(*terrain.get_pixel(4, 0), height_map.terrain(&registry, 0, 4).unwrap().colour());
+
+        assert_eq!(normal_texture(&height_map, 1.0).dimensions(), (5, 2));
+
+        let invalid = HeightMap::from_fn(1, 1, RANGE, |_, _| 1000);
+        assert_eq!(terrain_texture(&invalid, &registry).err(), Some(WorldGenError::UnknownHeight(1000)));
+    }
+
+    #[test]
+    fn textures_convert_to_bevy_images() {
+        let height_map = HeightMap::from_fn(3, 4, RANGE, |_, _| 50);
+        let img = to_bevy_image(normal_texture(&height_map, 1.0));
 
-/// Not very happy with this, but it'll do for now.
-/// 
-/// Saves an image of the given HeightMap to
-/// `demos/terrain_demo/` + `filename`
-pub fn texture_from_noise_map(
-    height_map: HeightMap,
-    filename: &str,
-) {
-    let img 
-        = ImageBuffer::from_fn(
-            height_map.get_width() as u32,
-            height_map.get_height() as u32,
-            |x, y| {    // Describes the value of every pixel
-                let terrain = 
-                    TerrainType::ident(
-                        &height_map.get(x as usize, y as usize)
-                    );
-                terrain.colour()
-        });
-    let path = String::from("demos/terrain_demo/") + filename;
-    println!("\nSaving image to path:\n\t{}\n\t...", path);
-
-    // Save the ImageBuffer
-    let res = img.save(path);
-
-    // If any error occured while saving the image, we print error
-    match res {
-        Ok(_) => println!("\tno errors saving image, we good 🚜\n"),
-        Err(e) => println!("Oh no\n{}", e),
+        assert_eq!(img.texture_descriptor.size.width, 4);
+        assert_eq!(img.texture_descriptor.size.height, 3);
+        assert_eq!(img.texture_descriptor.format, TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(img.data.len(), 3 * 4 * 4);
+        // Flat ground, fully opaque
+        assert_eq!(img.data[..4], [128, 128, 255, 255]);
     }
 }